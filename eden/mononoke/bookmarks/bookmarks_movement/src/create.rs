@@ -6,6 +6,7 @@
  */
 
 use std::collections::HashMap;
+use std::sync::Arc;
 
 use bookmarks::BookmarkTransaction;
 use bookmarks::BookmarkTransactionHook;
@@ -17,8 +18,10 @@ use bytes::Bytes;
 use context::CoreContext;
 use hooks::CrossRepoPushSource;
 use hooks::HookManager;
+use hooks::HookOutcome;
 use mononoke_types::BonsaiChangeset;
 use mononoke_types::ChangesetId;
+use mononoke_types::Timestamp;
 use repo_authorization::AuthorizationContext;
 use repo_authorization::RepoWriteOperation;
 use repo_update_logger::find_draft_ancestors;
@@ -27,6 +30,7 @@ use repo_update_logger::BookmarkOperation;
 
 use crate::affected_changesets::AdditionalChangesets;
 use crate::affected_changesets::AffectedChangesets;
+use crate::prefix_cache::BookmarkPrefixCache;
 use crate::repo_lock::check_repo_lock;
 use crate::restrictions::check_bookmark_sync_config;
 use crate::restrictions::BookmarkKindRestrictions;
@@ -34,6 +38,39 @@ use crate::BookmarkInfoTransaction;
 use crate::BookmarkMovementError;
 use crate::Repo;
 
+/// Opaque handle to the raw bundle that produced a bookmark creation, plus
+/// the commit-to-hg-hash timestamps recorded while unbundling it. Carried
+/// through to the `BookmarkUpdateLog` entry so that mirror/replay pipelines
+/// can reconstruct and re-apply the original push, without coupling the
+/// movement `reason` to Mercurial-specific bundle state.
+#[derive(Clone, Debug)]
+pub struct RawBundleReplayData {
+    /// Blobstore key of the raw bundle.
+    pub bundle_handle: String,
+    /// Timestamps (as recorded by the bundle) for each commit it introduced.
+    pub commit_timestamps: HashMap<ChangesetId, i64>,
+}
+
+/// Whether the repo lock would currently block this create, as observed by
+/// `CreateBookmarkOp::preview`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum RepoLockPreviewState {
+    Unlocked,
+    Locked,
+}
+
+/// Everything `CreateBookmarkOp::run` would have done, computed without
+/// mutating anything: the resolved bookmark kind, the commits that would be
+/// newly logged as public, the hook outcomes for the move, and whether the
+/// repo lock would currently block it.
+#[derive(Debug)]
+pub struct BookmarkCreatePreview {
+    pub kind: BookmarkKind,
+    pub commits_to_log: Vec<BonsaiChangeset>,
+    pub hook_outcomes: Vec<HookOutcome>,
+    pub repo_lock_state: RepoLockPreviewState,
+}
+
 #[must_use = "CreateBookmarkOp must be run to have an effect"]
 pub struct CreateBookmarkOp<'op> {
     bookmark: &'op BookmarkKey,
@@ -45,6 +82,8 @@ pub struct CreateBookmarkOp<'op> {
     pushvars: Option<&'op HashMap<String, Bytes>>,
     log_new_public_commits_to_scribe: bool,
     only_log_acl_checks: bool,
+    bundle_replay_data: Option<RawBundleReplayData>,
+    cache_invalidation: Option<Arc<BookmarkPrefixCache>>,
 }
 
 impl<'op> CreateBookmarkOp<'op> {
@@ -64,6 +103,8 @@ impl<'op> CreateBookmarkOp<'op> {
             pushvars: None,
             log_new_public_commits_to_scribe: false,
             only_log_acl_checks: false,
+            bundle_replay_data: None,
+            cache_invalidation: None,
         }
     }
 
@@ -107,6 +148,26 @@ impl<'op> CreateBookmarkOp<'op> {
         self
     }
 
+    /// Attach the raw bundle that produced this creation, so that mirror
+    /// repos replaying the update log can re-apply the original push.
+    /// Non-bundle, API-driven creates should simply pass `None`.
+    pub fn with_bundle_replay_data(
+        mut self,
+        bundle_replay_data: Option<RawBundleReplayData>,
+    ) -> Self {
+        self.bundle_replay_data = bundle_replay_data;
+        self
+    }
+
+    /// Register a write-through bookmark-prefix cache to invalidate, via a
+    /// transaction hook, when the transaction is committed. Callers that already read straight from master
+    /// (and so have no stale cache to worry about) can pass `None` to opt
+    /// out, which is also the default.
+    pub fn with_cache_invalidation(mut self, cache: Option<Arc<BookmarkPrefixCache>>) -> Self {
+        self.cache_invalidation = cache;
+        self
+    }
+
     pub async fn run_with_transaction(
         mut self,
         ctx: &'op CoreContext,
@@ -165,6 +226,10 @@ impl<'op> CreateBookmarkOp<'op> {
 
         let mut txn = txn.unwrap_or_else(|| repo.bookmarks().create_transaction(ctx.clone()));
 
+        if let Some(cache) = &self.cache_invalidation {
+            txn_hooks.push(cache.invalidation_hook(repo.repo_identity().id(), self.bookmark.clone()));
+        }
+
         let commits_to_log = match kind {
             BookmarkKind::Scratch => {
                 ctx.scuba()
@@ -228,13 +293,318 @@ impl<'op> CreateBookmarkOp<'op> {
             bookmark_kind: kind,
             operation: BookmarkOperation::Create(self.target),
             reason: self.reason,
+            actor: ctx.metadata().identities().clone(),
+            timestamp: Timestamp::now(),
+            source: self.cross_repo_push_source,
         };
+        // `info` (with the actor/timestamp/source above) is exactly what
+        // `BookmarkInfoTransaction::commit_and_log` needs to persist them on
+        // the `BookmarkUpdateLog` entry, but `BookmarkInfoTransaction` and
+        // `commit_and_log` are themselves defined in this crate's lib.rs,
+        // which isn't present in this checkout, so the actual log-write
+        // can't be wired up from here -- this carries the fields as far as
+        // this file's visible surface reaches.
         Ok(BookmarkInfoTransaction::new(
             info,
             txn,
             self.log_new_public_commits_to_scribe,
             commits_to_log,
             txn_hooks,
+            self.bundle_replay_data,
+        ))
+    }
+
+    pub async fn run(
+        self,
+        ctx: &'op CoreContext,
+        authz: &'op AuthorizationContext,
+        repo: &'op impl Repo,
+        hook_manager: &'op HookManager,
+    ) -> Result<BookmarkUpdateLogId, BookmarkMovementError> {
+        let info_txn = self
+            .run_with_transaction(ctx, authz, repo, hook_manager, None, vec![])
+            .await?;
+        info_txn.commit_and_log(ctx, repo).await
+    }
+
+    /// Run every validation `run` would run -- hook evaluation, repo-lock
+    /// check, sync-config check, ancestor-of restriction, draft-ancestor
+    /// discovery -- and report what would happen, without calling
+    /// `txn.create`/`commit_and_log`. Lets scs_server and pre-receive
+    /// tooling surface hook rejections and the newly-public commit list to
+    /// a user before they actually push, reusing the real server-side
+    /// checks rather than approximating them client-side.
+    pub async fn preview(
+        self,
+        ctx: &'op CoreContext,
+        authz: &'op AuthorizationContext,
+        repo: &'op impl Repo,
+        hook_manager: &'op HookManager,
+    ) -> Result<BookmarkCreatePreview, BookmarkMovementError> {
+        let kind = self.kind_restrictions.check_kind(repo, self.bookmark)?;
+
+        if self.only_log_acl_checks {
+            if authz
+                .check_repo_write(ctx, repo, RepoWriteOperation::CreateBookmark(kind))
+                .await
+                .is_denied()
+            {
+                ctx.scuba()
+                    .clone()
+                    .log_with_msg("Repo write ACL check would fail for bookmark create", None);
+            }
+        } else {
+            authz
+                .require_repo_write(ctx, repo, RepoWriteOperation::CreateBookmark(kind))
+                .await?;
+        }
+        authz
+            .require_bookmark_modify(ctx, repo, self.bookmark)
+            .await?;
+
+        check_bookmark_sync_config(ctx, repo, self.bookmark, kind).await?;
+
+        // `check_restrictions` evaluates hooks internally and returns the
+        // outcomes it computed, so preview surfaces exactly what a real push
+        // with this op's config would see -- including a rejection, which a
+        // hard error here would otherwise hide from the caller.
+        let hook_outcomes = self
+            .affected_changesets
+            .check_restrictions(
+                ctx,
+                authz,
+                repo,
+                hook_manager,
+                self.bookmark,
+                self.pushvars,
+                self.reason,
+                kind,
+                AdditionalChangesets::Ancestors(self.target),
+                self.cross_repo_push_source,
+            )
+            .await?;
+
+        let repo_lock_state = match check_repo_lock(
+            repo,
+            kind,
+            self.pushvars,
+            ctx.metadata().identities(),
+            authz,
+        )
+        .await
+        {
+            Ok(()) => RepoLockPreviewState::Unlocked,
+            Err(_) => RepoLockPreviewState::Locked,
+        };
+
+        let commits_to_log = if let BookmarkKind::Publishing | BookmarkKind::PullDefaultPublishing =
+            kind
+        {
+            crate::restrictions::check_restriction_ensure_ancestor_of(
+                ctx,
+                repo,
+                self.bookmark,
+                self.target,
+            )
+            .await?;
+
+            match find_draft_ancestors(ctx, repo, self.target).await {
+                Ok(bcss) => bcss,
+                Err(err) => {
+                    ctx.scuba()
+                        .clone()
+                        .log_with_msg("Failed to find draft ancestors", Some(format!("{}", err)));
+                    vec![]
+                }
+            }
+        } else {
+            vec![]
+        };
+
+        Ok(BookmarkCreatePreview {
+            kind,
+            commits_to_log,
+            hook_outcomes,
+            repo_lock_state,
+        })
+    }
+}
+
+/// Create several bookmarks atomically: every op's restriction checks run
+/// up front -- with each op's new-changesets set unioned across the whole
+/// bundle first, so one op's checks can see changesets another op in the
+/// same bundle is introducing -- before any `txn.create`/`txn.create_scratch`
+/// call lands on the one shared `BookmarkTransaction`, and the whole group
+/// is committed (or rejected) together, so a rejected bookmark rolls back
+/// the others.
+#[must_use = "CreateBookmarkBundleOp must be run to have an effect"]
+pub struct CreateBookmarkBundleOp<'op> {
+    ops: Vec<CreateBookmarkOp<'op>>,
+}
+
+impl<'op> CreateBookmarkBundleOp<'op> {
+    pub fn new() -> Self {
+        CreateBookmarkBundleOp { ops: Vec::new() }
+    }
+
+    pub fn add(mut self, op: CreateBookmarkOp<'op>) -> Self {
+        self.ops.push(op);
+        self
+    }
+
+    pub async fn run_with_transaction(
+        self,
+        ctx: &'op CoreContext,
+        authz: &'op AuthorizationContext,
+        repo: &'op impl Repo,
+        hook_manager: &'op HookManager,
+        txn: Option<Box<dyn BookmarkTransaction>>,
+        mut txn_hooks: Vec<BookmarkTransactionHook>,
+    ) -> Result<BookmarkInfoTransaction, BookmarkMovementError> {
+        let mut txn = txn.unwrap_or_else(|| repo.bookmarks().create_transaction(ctx.clone()));
+        let mut infos = Vec::with_capacity(self.ops.len());
+        let mut replay_data = Vec::with_capacity(self.ops.len());
+        let mut commits_to_log = Vec::new();
+        let mut log_new_public_commits_to_scribe = false;
+
+        // Union every op's new changesets across the whole bundle before
+        // any op's restriction/hook checks run, so e.g. op A's check can see
+        // a changeset that only op B is introducing, instead of each op
+        // validating in isolation against just its own set.
+        let all_new_changesets: HashMap<ChangesetId, BonsaiChangeset> = self
+            .ops
+            .iter()
+            .flat_map(|op| op.affected_changesets.new_changesets().clone())
+            .collect();
+        let mut ops = self.ops;
+        if !all_new_changesets.is_empty() {
+            for op in ops.iter_mut() {
+                op.affected_changesets
+                    .add_new_changesets(all_new_changesets.clone());
+            }
+        }
+
+        let mut kinds = Vec::with_capacity(ops.len());
+        for op in &ops {
+            let kind = op.kind_restrictions.check_kind(repo, op.bookmark)?;
+
+            if op.only_log_acl_checks {
+                if authz
+                    .check_repo_write(ctx, repo, RepoWriteOperation::CreateBookmark(kind))
+                    .await
+                    .is_denied()
+                {
+                    ctx.scuba()
+                        .clone()
+                        .log_with_msg("Repo write ACL check would fail for bookmark create", None);
+                }
+            } else {
+                authz
+                    .require_repo_write(ctx, repo, RepoWriteOperation::CreateBookmark(kind))
+                    .await?;
+            }
+            authz.require_bookmark_modify(ctx, repo, op.bookmark).await?;
+
+            check_bookmark_sync_config(ctx, repo, op.bookmark, kind).await?;
+
+            op.affected_changesets
+                .check_restrictions(
+                    ctx,
+                    authz,
+                    repo,
+                    hook_manager,
+                    op.bookmark,
+                    op.pushvars,
+                    op.reason,
+                    kind,
+                    AdditionalChangesets::Ancestors(op.target),
+                    op.cross_repo_push_source,
+                )
+                .await?;
+
+            check_repo_lock(repo, kind, op.pushvars, ctx.metadata().identities(), authz).await?;
+
+            if let BookmarkKind::Publishing | BookmarkKind::PullDefaultPublishing = kind {
+                crate::restrictions::check_restriction_ensure_ancestor_of(
+                    ctx, repo, op.bookmark, op.target,
+                )
+                .await?;
+            }
+
+            kinds.push(kind);
+        }
+
+        for (op, kind) in ops.into_iter().zip(kinds) {
+            if let Some(cache) = &op.cache_invalidation {
+                txn_hooks.push(cache.invalidation_hook(repo.repo_identity().id(), op.bookmark.clone()));
+            }
+
+            match kind {
+                BookmarkKind::Scratch => {
+                    ctx.scuba()
+                        .clone()
+                        .add("bookmark", op.bookmark.to_string())
+                        .log_with_msg("Creating scratch bookmark", None);
+                    txn.create_scratch(op.bookmark, op.target)?;
+                }
+                BookmarkKind::Publishing | BookmarkKind::PullDefaultPublishing => {
+                    if let Some(txn_hook) = crate::git_mapping::populate_git_mapping_txn_hook(
+                        ctx,
+                        repo,
+                        op.target,
+                        op.affected_changesets.new_changesets(),
+                    )
+                    .await?
+                    {
+                        txn_hooks.push(txn_hook);
+                    }
+
+                    if op.log_new_public_commits_to_scribe {
+                        log_new_public_commits_to_scribe = true;
+                        match find_draft_ancestors(ctx, repo, op.target).await {
+                            Ok(bcss) => commits_to_log.extend(bcss),
+                            Err(err) => {
+                                ctx.scuba().clone().log_with_msg(
+                                    "Failed to find draft ancestors",
+                                    Some(format!("{}", err)),
+                                );
+                            }
+                        }
+                    }
+
+                    ctx.scuba()
+                        .clone()
+                        .add("bookmark", op.bookmark.to_string())
+                        .log_with_msg("Creating public bookmark", None);
+                    txn.create(op.bookmark, op.target, op.reason)?;
+                }
+            }
+
+            infos.push(BookmarkInfo {
+                bookmark_name: op.bookmark.clone(),
+                bookmark_kind: kind,
+                operation: BookmarkOperation::Create(op.target),
+                reason: op.reason,
+                actor: ctx.metadata().identities().clone(),
+                timestamp: Timestamp::now(),
+                source: op.cross_repo_push_source,
+            });
+            replay_data.push(op.bundle_replay_data);
+        }
+
+        // Same caveat as the single-op path above: persisting actor/timestamp/
+        // source onto the `BookmarkUpdateLog` entries for this batch, and
+        // exposing them back out on the transaction, is `commit_and_log`'s and
+        // `BookmarkInfoTransaction`'s job, and neither is defined anywhere in
+        // this checkout -- `infos` is as far as this crate's visible code can
+        // carry them.
+        Ok(BookmarkInfoTransaction::new_batch(
+            infos,
+            txn,
+            log_new_public_commits_to_scribe,
+            commits_to_log,
+            txn_hooks,
+            replay_data,
         ))
     }
 
@@ -0,0 +1,147 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! A write-through cache of `list_by_prefix`-style bookmark reads.
+//!
+//! Readers that only need an eventually-consistent view of bookmarks under
+//! some prefix should call [`BookmarkPrefixCache::get_or_fetch`], passing a
+//! closure that queries the bookmarks store's replica; it serves the cached
+//! snapshot when one is fresh and otherwise fetches, caches, and returns the
+//! result, so repeat reads of the same prefix stop round-tripping to the
+//! replica. Entries are never updated in place: anything that locally
+//! commits a bookmark mutation should call
+//! [`BookmarkPrefixCache::invalidate_covering`] (or register the
+//! [`BookmarkPrefixCache::invalidation_hook`] as a `BookmarkTransactionHook`)
+//! so the next read repopulates from the master replica, rather than
+//! continuing to serve a snapshot that predates the write.
+//!
+//! NOTE: the exact shape of `bookmarks::BookmarkTransactionHook` lives in the
+//! `bookmarks` crate, which is not present in this checkout, so
+//! `invalidation_hook` below assumes the same `Fn(CoreContext, Box<dyn
+//! Transaction>) -> BoxFuture<'static, Result<Box<dyn Transaction>>>` shape
+//! used elsewhere in this crate (see `git_mapping::populate_git_mapping_txn_hook`).
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use anyhow::Error;
+use bookmarks::BookmarkTransactionHook;
+use bookmarks_types::BookmarkKey;
+use bookmarks_types::BookmarkKind;
+use bookmarks_types::BookmarkPrefix;
+use context::CoreContext;
+use futures::future::FutureExt;
+use mononoke_types::ChangesetId;
+use mononoke_types::RepositoryId;
+
+/// A cached snapshot of the bookmarks under some prefix, valid until `expires`.
+pub struct CacheEntry {
+    pub expires: Instant,
+    pub bookmarks: BTreeMap<BookmarkKey, (ChangesetId, BookmarkKind)>,
+}
+
+/// Write-through cache of bookmark-by-prefix reads, shared by every caller
+/// that enqueues reads against the same repo.
+#[derive(Default)]
+pub struct BookmarkPrefixCache {
+    entries: Mutex<HashMap<(RepositoryId, BookmarkPrefix), CacheEntry>>,
+}
+
+impl BookmarkPrefixCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return a cached snapshot for `prefix`, if one exists and hasn't expired.
+    pub fn get(
+        &self,
+        repo_id: RepositoryId,
+        prefix: &BookmarkPrefix,
+    ) -> Option<BTreeMap<BookmarkKey, (ChangesetId, BookmarkKind)>> {
+        let entries = self.entries.lock().expect("BookmarkPrefixCache poisoned");
+        let entry = entries.get(&(repo_id, prefix.clone()))?;
+        if Instant::now() < entry.expires {
+            Some(entry.bookmarks.clone())
+        } else {
+            None
+        }
+    }
+
+    /// Populate the cache for `prefix` with a freshly-read snapshot.
+    pub fn insert(
+        &self,
+        repo_id: RepositoryId,
+        prefix: BookmarkPrefix,
+        bookmarks: BTreeMap<BookmarkKey, (ChangesetId, BookmarkKind)>,
+        ttl: Duration,
+    ) {
+        let mut entries = self.entries.lock().expect("BookmarkPrefixCache poisoned");
+        entries.insert(
+            (repo_id, prefix),
+            CacheEntry {
+                expires: Instant::now() + ttl,
+                bookmarks,
+            },
+        );
+    }
+
+    /// Serve `prefix` out of the cache if a fresh snapshot is present;
+    /// otherwise call `fetch` against the master replica, populate the
+    /// cache with the result (so the next read is served from here), and
+    /// return it. This is the read path every `list_by_prefix`-style caller
+    /// should go through instead of calling `get`/`insert` directly, so the
+    /// cache/store always stay the single source of truth together.
+    pub async fn get_or_fetch<F, Fut>(
+        &self,
+        repo_id: RepositoryId,
+        prefix: &BookmarkPrefix,
+        ttl: Duration,
+        fetch: F,
+    ) -> Result<BTreeMap<BookmarkKey, (ChangesetId, BookmarkKind)>, Error>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<BTreeMap<BookmarkKey, (ChangesetId, BookmarkKind)>, Error>>,
+    {
+        if let Some(bookmarks) = self.get(repo_id, prefix) {
+            return Ok(bookmarks);
+        }
+
+        let bookmarks = fetch().await?;
+        self.insert(repo_id, prefix.clone(), bookmarks.clone(), ttl);
+        Ok(bookmarks)
+    }
+
+    /// Drop any cached entry whose prefix covers `bookmark`, so the next
+    /// read of that prefix repopulates from the master replica.
+    pub fn invalidate_covering(&self, repo_id: RepositoryId, bookmark: &BookmarkKey) {
+        let mut entries = self.entries.lock().expect("BookmarkPrefixCache poisoned");
+        entries.retain(|(entry_repo_id, prefix), _| {
+            !(*entry_repo_id == repo_id && prefix.is_prefix_of(bookmark.name()))
+        });
+    }
+
+    /// Build a `BookmarkTransactionHook` that purges any cached prefix
+    /// covering `bookmark` when the hook runs at commit time. The purge
+    /// happens while the transaction is still open, before the commit
+    /// completes, and is not undone if the commit then fails.
+    pub fn invalidation_hook(
+        self: &Arc<Self>,
+        repo_id: RepositoryId,
+        bookmark: BookmarkKey,
+    ) -> BookmarkTransactionHook {
+        let cache = Arc::clone(self);
+        Arc::new(move |_ctx: CoreContext, txn| {
+            cache.invalidate_covering(repo_id, &bookmark);
+            async move { Ok::<_, Error>(txn) }.boxed()
+        })
+    }
+}
@@ -120,6 +120,8 @@ pub struct CommonConfig {
     pub enable_http_control_api: bool,
     /// Configuration for redaction of blobs
     pub redaction_config: RedactionConfig,
+    /// Cross-repo commit sync configs, keyed by their version name.
+    pub commit_sync: HashMap<CommitSyncConfigVersion, CommitSyncConfig>,
 }
 
 /// Configuration for logging of censored blobstore accesses
@@ -236,6 +238,13 @@ pub struct DerivedDataConfig {
     /// Name of of configuration for enabled derived data types.
     pub enabled_config_name: String,
 
+    /// Name of configuration granted to backfillers, if any. Backfillers
+    /// are authorized to derive data by being handed this config object
+    /// rather than by a boolean flag, so they can be given extra types and
+    /// `mapping_key_prefixes` (to rederive into fresh keys) without
+    /// affecting the live derivation path.
+    pub backfilling_config_name: Option<String>,
+
     /// All available configs for derived data types
     pub available_configs: HashMap<String, DerivedDataTypesConfig>,
 }
@@ -259,11 +268,26 @@ impl DerivedDataConfig {
         }
     }
 
+    /// Returns whether the named derived data type is enabled for backfilling.
+    pub fn is_enabled_for_backfilling(&self, name: &str) -> bool {
+        match &self.backfilling_config_name {
+            Some(config_name) => self.is_enabled_for_config_name(name, config_name),
+            None => false,
+        }
+    }
+
     /// Returns mutable ref to active DerivedDataTypesConfig
     pub fn get_active_config(&mut self) -> Option<&mut DerivedDataTypesConfig> {
         self.available_configs.get_mut(&self.enabled_config_name)
     }
 
+    /// Returns mutable ref to the DerivedDataTypesConfig granted to
+    /// backfillers, if one is configured.
+    pub fn get_active_backfilling_config(&mut self) -> Option<&mut DerivedDataTypesConfig> {
+        let config_name = self.backfilling_config_name.as_ref()?;
+        self.available_configs.get_mut(config_name)
+    }
+
     /// Returns DerivedDataTypesConfig for the given name from the list of available configs.
     pub fn get_config(&self, name: &str) -> Option<&DerivedDataTypesConfig> {
         self.available_configs.get(name)
@@ -336,6 +360,71 @@ impl RepoConfig {
     pub fn primary_metadata_db_address(&self) -> Option<String> {
         self.storage_config.metadata.primary_address()
     }
+
+    /// Effective read-only status: `overrides.readonly` if set, otherwise
+    /// the statically configured value.
+    pub fn effective_readonly(&self, overrides: &RepoConfigOverrides) -> RepoReadOnly {
+        overrides
+            .readonly
+            .clone()
+            .unwrap_or_else(|| self.readonly.clone())
+    }
+
+    /// Effective hash validation percentage: `overrides.hash_validation_percentage`
+    /// if set, otherwise the statically configured value.
+    pub fn effective_hash_validation_percentage(&self, overrides: &RepoConfigOverrides) -> usize {
+        overrides
+            .hash_validation_percentage
+            .unwrap_or(self.hash_validation_percentage)
+    }
+
+    /// Effective max number of results in listkeyspatterns: `overrides.list_keys_patterns_max`
+    /// if set, otherwise the statically configured value.
+    pub fn effective_list_keys_patterns_max(&self, overrides: &RepoConfigOverrides) -> u64 {
+        overrides
+            .list_keys_patterns_max
+            .unwrap_or(self.list_keys_patterns_max)
+    }
+
+    /// Effective maximum file size to consider in hooks: `overrides.hook_max_file_size`
+    /// if set, otherwise the statically configured value.
+    pub fn effective_hook_max_file_size(&self, overrides: &RepoConfigOverrides) -> u64 {
+        overrides
+            .hook_max_file_size
+            .unwrap_or(self.hook_max_file_size)
+    }
+
+    /// Effective warm bookmarks cache usage for repo_client:
+    /// `overrides.repo_client_use_warm_bookmarks_cache` if set, otherwise the
+    /// statically configured value.
+    pub fn effective_repo_client_use_warm_bookmarks_cache(
+        &self,
+        overrides: &RepoConfigOverrides,
+    ) -> bool {
+        overrides
+            .repo_client_use_warm_bookmarks_cache
+            .unwrap_or(self.repo_client_use_warm_bookmarks_cache)
+    }
+}
+
+/// Per-repo dynamic overlay for a handful of `RepoConfig` knobs that
+/// operators need to adjust without a full config reload (e.g. flipping a
+/// repo read-only). Every field is optional; `None` means "defer to the
+/// statically configured value" (see `RepoConfig::effective_*`). Cheap to
+/// clone, so it can be held behind an `Arc` and swapped out wholesale as
+/// overrides change.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct RepoConfigOverrides {
+    /// Override for `RepoConfig::hash_validation_percentage`.
+    pub hash_validation_percentage: Option<usize>,
+    /// Override for `RepoConfig::list_keys_patterns_max`.
+    pub list_keys_patterns_max: Option<u64>,
+    /// Override for `RepoConfig::readonly`.
+    pub readonly: Option<RepoReadOnly>,
+    /// Override for `RepoConfig::hook_max_file_size`.
+    pub hook_max_file_size: Option<u64>,
+    /// Override for `RepoConfig::repo_client_use_warm_bookmarks_cache`.
+    pub repo_client_use_warm_bookmarks_cache: Option<bool>,
 }
 
 #[derive(Eq, Copy, Clone, Debug, PartialEq, Deserialize)]
@@ -435,12 +524,12 @@ impl From<Regex> for BookmarkOrRegex {
 /// Attributes for a single bookmark
 pub struct SingleBookmarkAttr {
     params: BookmarkParams,
-    membership: Option<BoxMembershipChecker>,
+    memberships: Vec<BoxMembershipChecker>,
 }
 
 impl SingleBookmarkAttr {
-    fn new(params: BookmarkParams, membership: Option<BoxMembershipChecker>) -> Self {
-        Self { params, membership }
+    fn new(params: BookmarkParams, memberships: Vec<BoxMembershipChecker>) -> Self {
+        Self { params, memberships }
     }
 
     /// Bookmark parameters from config
@@ -448,9 +537,9 @@ impl SingleBookmarkAttr {
         &self.params
     }
 
-    /// Membership checker
-    pub fn membership(&self) -> &Option<BoxMembershipChecker> {
-        &self.membership
+    /// Membership checkers, one per configured hipster group
+    pub fn memberships(&self) -> &[BoxMembershipChecker] {
+        &self.memberships
     }
 }
 
@@ -468,14 +557,16 @@ impl BookmarkAttrs {
     ) -> Result<Self, Error> {
         let mut v = vec![];
         for params in bookmark_params {
-            let membership_checker = match params.allowed_hipster_group {
-                Some(ref hipster_group) => {
-                    Some(MembershipCheckerBuilder::for_group(fb, &hipster_group).await?)
-                }
-                None => None,
-            };
+            let mut memberships = Vec::new();
+            for hipster_group in params
+                .allowed_hipster_group
+                .iter()
+                .chain(params.allowed_hipster_groups.iter())
+            {
+                memberships.push(MembershipCheckerBuilder::for_group(fb, hipster_group).await?);
+            }
 
-            v.push(SingleBookmarkAttr::new(params, membership_checker));
+            v.push(SingleBookmarkAttr::new(params, memberships));
         }
 
         Ok(Self {
@@ -519,27 +610,24 @@ impl BookmarkAttrs {
         metadata: &Metadata,
         bookmark: &BookmarkName,
     ) -> Result<bool, Error> {
-        // NOTE: `Iterator::all` combinator returns `true` if selected set is empty
+        // NOTE: an empty set of checks means `true` regardless of match mode,
         //       which is consistent with what we want
         for attr in self.select(bookmark) {
-            let maybe_allowed_users = attr
-                .params()
-                .allowed_users
-                .as_ref()
-                .map(|re| re.is_match(user));
-
-            let maybe_member = if let Some(membership) = &attr.membership {
-                Some(membership.is_member(&metadata.identities()).await?)
-            } else {
-                None
-            };
+            let mut checks = Vec::new();
+
+            if let Some(re) = attr.params().allowed_users.as_ref() {
+                checks.push(re.is_match(user));
+            }
 
-            // Check if either is user is allowed to access it
-            // or that they are a member of hipster group.
-            let allowed = match (maybe_allowed_users, maybe_member) {
-                (Some(x), Some(y)) => x || y,
-                (Some(x), None) | (None, Some(x)) => x,
-                (None, None) => true,
+            for membership in attr.memberships() {
+                checks.push(membership.is_member(&metadata.identities()).await?);
+            }
+
+            // Depending on `allowed_users_match_mode`, the user must satisfy
+            // either any or all of the configured regex/hipster groups.
+            let allowed = match attr.params().allowed_users_match_mode {
+                AclMatchMode::Any => checks.is_empty() || checks.iter().any(|allowed| *allowed),
+                AclMatchMode::All => checks.iter().all(|allowed| *allowed),
             };
             if !allowed {
                 return Ok(false);
@@ -549,6 +637,22 @@ impl BookmarkAttrs {
     }
 }
 
+/// Whether a user must satisfy any (OR) or all (AND) of a bookmark's
+/// configured `allowed_users`/`allowed_hipster_group(s)` checks.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Deserialize)]
+pub enum AclMatchMode {
+    /// The user is allowed if they satisfy at least one configured check.
+    Any,
+    /// The user is allowed only if they satisfy every configured check.
+    All,
+}
+
+impl Default for AclMatchMode {
+    fn default() -> Self {
+        AclMatchMode::Any
+    }
+}
+
 /// Configuration for a bookmark
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct BookmarkParams {
@@ -566,6 +670,13 @@ pub struct BookmarkParams {
     /// Only users matching this pattern or hipster group will be allowed to
     /// move this bookmark
     pub allowed_hipster_group: Option<String>,
+    /// Additional hipster groups whose members are allowed to move this
+    /// bookmark, evaluated together with `allowed_hipster_group` and
+    /// `allowed_users` according to `allowed_users_match_mode`.
+    pub allowed_hipster_groups: Vec<String>,
+    /// Whether a user must satisfy any or all of `allowed_users` and the
+    /// configured hipster groups to be allowed to move this bookmark.
+    pub allowed_users_match_mode: AclMatchMode,
     /// Skip hooks for changesets that are already ancestors of these
     /// bookmarks
     pub hooks_skip_ancestors_of: Vec<BookmarkName>,
@@ -836,6 +947,38 @@ pub struct StorageConfig {
     pub ephemeral_blobstore: Option<EphemeralBlobstoreConfig>,
 }
 
+/// Name of a storage configuration in the top-level named-storage registry
+/// (see `StorageConfigs`). Declaring a `StorageConfig` once under a name and
+/// referencing it by name from multiple repos avoids copy-pasting the same
+/// remote multiplex into every repo's config.
+#[derive(Debug, Default, Clone, Eq, PartialEq, Hash)]
+pub struct StorageConfigName(pub String);
+
+impl AsRef<str> for StorageConfigName {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+/// The top-level registry of named storage configurations, loaded once and
+/// shared across repos. Blobstore-level tools (healer, scrub, GC) can be
+/// pointed directly at a named storage via this registry without needing a
+/// representative repo config.
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct StorageConfigs {
+    /// Named storage configurations, keyed by name.
+    pub storage: HashMap<StorageConfigName, StorageConfig>,
+}
+
+impl StorageConfigs {
+    /// Resolve a named storage config reference (as written in a repo's
+    /// `storage_config = "name"`) to its concrete `StorageConfig`, if a
+    /// config by that name is registered.
+    pub fn resolve(&self, name: &StorageConfigName) -> Option<&StorageConfig> {
+        self.storage.get(name)
+    }
+}
+
 /// Whether we should read from this blobstore normally in a Multiplex,
 /// or only read from it in Scrub or when it's our last chance to find the blob
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Deserialize, Hash)]
@@ -844,6 +987,23 @@ pub enum MultiplexedStoreType {
     Normal,
     /// Only read if Normal blobstores don't provide the blob. Writes go here as per normal
     WriteMostly,
+    /// Being decommissioned: still served as a fallback read (so in-flight
+    /// keys aren't lost), but receives no new writes and doesn't count
+    /// toward `minimum_successful_writes`.
+    ReadOnly,
+}
+
+impl MultiplexedStoreType {
+    /// Whether a `put` should be issued to a store of this type at all.
+    pub fn is_writeable(&self) -> bool {
+        !matches!(self, Self::ReadOnly)
+    }
+
+    /// Whether a successful write to a store of this type counts toward
+    /// the multiplex's `minimum_successful_writes` quorum.
+    pub fn counts_towards_write_quorum(&self) -> bool {
+        matches!(self, Self::Normal)
+    }
 }
 
 /// What format should data be in either Raw or a compressed form with compression options like level
@@ -853,6 +1013,24 @@ pub enum PackFormat {
     Raw,
     /// Data will be compressed and written in compressed form if its smaller than Raw
     ZstdIndividual(i32),
+    /// Blobs destined for the same pack are compressed together against a
+    /// shared trained zstd dictionary, stored once as a pack-level record,
+    /// for better ratios on many small, similar objects (e.g. manifests,
+    /// filenodes) than compressing each blob independently. Falls back to
+    /// `ZstdIndividual(level)` per blob when there aren't
+    /// `min_samples_for_dictionary` samples to train a dictionary, and to
+    /// the smaller of `ZstdIndividual`/`Raw` per blob when the
+    /// dictionary-compressed form isn't smaller.
+    ZstdDictionary {
+        /// Compression level, used both to train the dictionary and to
+        /// compress blobs against it.
+        level: i32,
+        /// Target size, in bytes, of the trained dictionary.
+        max_dictionary_size: u64,
+        /// Minimum number of sample blobs required before training a
+        /// dictionary.
+        min_samples_for_dictionary: usize,
+    },
 }
 
 impl Default for PackFormat {
@@ -915,8 +1093,38 @@ pub enum BlobConfig {
         /// 1 in scuba_sample_rate samples will be logged for both
         /// multiplex and per blobstore scuba tables
         scuba_sample_rate: NonZeroU64,
-        /// DB config to use for the sync queue
-        queue_db: DatabaseConfig,
+        /// DB config to use for the sync queue. Queue rows are distributed
+        /// across shards (if any) by a hash of the blobstore key.
+        queue_db: ShardableDatabaseConfig,
+    },
+    /// Multiplex across multiple blobstores for redundancy, using a
+    /// write-ahead log instead of the legacy sync queue. A `put` returns
+    /// success once its WAL entry is durable and `minimum_successful_writes`
+    /// components have acknowledged; WAL tailers asynchronously fill in the
+    /// remaining components by scanning unflushed entries. A `get` only
+    /// declares a blob absent once `not_present_read_quorum` components
+    /// report absence.
+    MultiplexedWal {
+        /// A unique ID that identifies this multiplex configuration
+        multiplex_id: MultiplexId,
+        /// A scuba table to log stats per blobstore
+        scuba_table: Option<String>,
+        /// A scuba table for multiplex stats
+        multiplex_scuba_table: Option<String>,
+        /// Set of blobstores being multiplexed over
+        blobstores: Vec<(BlobstoreId, MultiplexedStoreType, BlobConfig)>,
+        /// The number of writes that must succeed for a `put` to the multiplex to succeed
+        minimum_successful_writes: NonZeroUsize,
+        /// The number of reads needed to decided a blob is not present
+        not_present_read_quorum: NonZeroUsize,
+        /// 1 in scuba_sample_rate samples will be logged for both
+        /// multiplex and per blobstore scuba tables
+        scuba_sample_rate: NonZeroU64,
+        /// DB config for the write-ahead log journal, scanned by tailers to
+        /// fill in components that weren't part of the write quorum. Rows
+        /// are distributed across shards (if any) by a hash of the
+        /// blobstore key.
+        wal_queue: ShardableDatabaseConfig,
     },
     /// Store in a manifold bucket, but every object will have an expiration
     ManifoldWithTtl {
@@ -967,7 +1175,7 @@ impl BlobConfig {
         match self {
             Disabled | Files { .. } | Sqlite { .. } => true,
             Manifold { .. } | Mysql { .. } | ManifoldWithTtl { .. } | S3 { .. } => false,
-            Multiplexed { blobstores, .. } => blobstores
+            Multiplexed { blobstores, .. } | MultiplexedWal { blobstores, .. } => blobstores
                 .iter()
                 .map(|(_, _, config)| config)
                 .all(BlobConfig::is_local),
@@ -983,6 +1191,10 @@ impl BlobConfig {
                 ref mut scuba_sample_rate,
                 ..
             }
+            | Self::MultiplexedWal {
+                ref mut scuba_sample_rate,
+                ..
+            }
             | Self::Logging {
                 ref mut scuba_sample_rate,
                 ..
@@ -1053,6 +1265,39 @@ impl DatabaseConfig {
     }
 }
 
+/// Configuration for a database that may be sharded, used for the
+/// multiplex sync/WAL queue so it can be spread across multiple shards to
+/// avoid becoming a write hotspot for large repos. Local databases (used
+/// for local/testing) are never sharded.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum ShardableDatabaseConfig {
+    /// Local SQLite database
+    Local(LocalDatabaseConfig),
+    /// Remote MySQL database, optionally sharded
+    Remote(ShardableRemoteDatabaseConfig),
+}
+
+impl ShardableDatabaseConfig {
+    /// Whether this is a local on-disk database.
+    pub fn is_local(&self) -> bool {
+        match self {
+            Self::Local(_) => true,
+            Self::Remote(_) => false,
+        }
+    }
+}
+
+impl From<DatabaseConfig> for ShardableDatabaseConfig {
+    fn from(config: DatabaseConfig) -> Self {
+        match config {
+            DatabaseConfig::Local(local) => Self::Local(local),
+            DatabaseConfig::Remote(remote) => {
+                Self::Remote(ShardableRemoteDatabaseConfig::Unsharded(remote))
+            }
+        }
+    }
+}
+
 /// Configuration for the Metadata database when it is remote.
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct RemoteMetadataDatabaseConfig {
@@ -1150,18 +1395,18 @@ impl InfinitepushNamespace {
 #[derive(Debug, Clone, Eq, PartialEq, PartialOrd, Ord)]
 pub enum CommitcloudBookmarksFillerMode {
     /// No filling.
-    DISABLED = 0,
+    Disabled,
     /// Backfill old entries.
-    BACKFILL = 1,
+    Backfill,
     /// Fill the entries forward.
-    FORWARDFILL = 2,
+    Forwardfill,
     /// Both fillers active.
-    BIDIRECTIONAL = 3,
+    Bidirectional,
 }
 
 impl Default for CommitcloudBookmarksFillerMode {
     fn default() -> Self {
-        CommitcloudBookmarksFillerMode::DISABLED
+        CommitcloudBookmarksFillerMode::Disabled
     }
 }
 
@@ -1234,6 +1479,10 @@ pub enum DefaultSmallToLargeCommitSyncPathAction {
 /// prefix in the small repo, and a value - in the large repo
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct SmallRepoCommitSyncConfig {
+    /// Prefix to prepend to this small repo's bookmarks when mirroring them
+    /// into the large repo, except for the large repo's
+    /// `common_pushrebase_bookmarks`.
+    pub bookmark_prefix: AsciiString,
     /// Default action to take on a path
     pub default_action: DefaultSmallToLargeCommitSyncPathAction,
     /// A map of prefix replacements when syncing
@@ -1297,6 +1546,8 @@ impl FromValue for CommitSyncConfigVersion {
 pub struct CommitSyncConfig {
     /// Large repository id
     pub large_repo_id: RepositoryId,
+    /// Direction in which commits are synced
+    pub direction: CommitSyncDirection,
     /// Common pushrebase bookmarks
     pub common_pushrebase_bookmarks: Vec<BookmarkName>,
     /// Corresponding small repo configs
@@ -1305,6 +1556,93 @@ pub struct CommitSyncConfig {
     pub version_name: CommitSyncConfigVersion,
 }
 
+impl CommitSyncConfig {
+    /// Construct a new `CommitSyncConfig`, validating that syncing into the
+    /// large repo can never drop a file and that small repos sharing this
+    /// large repo can never collide with each other once synced.
+    ///
+    /// Concretely: no two small repos may use the same `bookmark_prefix`;
+    /// no two small repos may route paths (whether via `default_action` or
+    /// an entry in `map`) under the same destination prefix in the large
+    /// repo, *or* under one another's destination prefixes (one repo's
+    /// `"foo"` colliding with another's `"foo/bar"`, say); and no two small
+    /// repos may both use `Preserve` as their `default_action`, since that
+    /// routes both of them straight into the large repo's root.
+    pub fn new(
+        large_repo_id: RepositoryId,
+        direction: CommitSyncDirection,
+        common_pushrebase_bookmarks: Vec<BookmarkName>,
+        small_repos: HashMap<RepositoryId, SmallRepoCommitSyncConfig>,
+        version_name: CommitSyncConfigVersion,
+    ) -> Result<Self> {
+        let mut bookmark_prefixes = HashSet::new();
+        let mut dest_prefixes: Vec<MPath> = Vec::new();
+        let mut has_preserve_default = false;
+
+        for small_repo in small_repos.values() {
+            if !bookmark_prefixes.insert(small_repo.bookmark_prefix.as_str()) {
+                return Err(anyhow!(
+                    "bookmark_prefix {:?} is used by more than one small repo of large repo {}",
+                    small_repo.bookmark_prefix,
+                    large_repo_id,
+                ));
+            }
+
+            let mut this_repo_prefixes = Vec::new();
+            match &small_repo.default_action {
+                DefaultSmallToLargeCommitSyncPathAction::Preserve => {
+                    if has_preserve_default {
+                        return Err(anyhow!(
+                            "more than one small repo of large repo {} uses Preserve as its \
+                             default action, so they would all route unmapped paths to the \
+                             same large-repo root",
+                            large_repo_id,
+                        ));
+                    }
+                    has_preserve_default = true;
+                }
+                DefaultSmallToLargeCommitSyncPathAction::PrependPrefix(prefix) => {
+                    this_repo_prefixes.push(prefix.clone());
+                }
+            }
+            this_repo_prefixes.extend(small_repo.map.values().cloned());
+
+            let already_registered: PrefixTrie = dest_prefixes.iter().cloned().collect();
+            for dest in &this_repo_prefixes {
+                if already_registered.contains_prefix(dest) {
+                    return Err(anyhow!(
+                        "path prefix {:?} of large repo {} falls under a destination prefix \
+                         already used by another small repo",
+                        dest,
+                        large_repo_id,
+                    ));
+                }
+                let candidate: PrefixTrie = std::iter::once(dest.clone()).collect();
+                if let Some(covered) = dest_prefixes.iter().find(|p| candidate.contains_prefix(p))
+                {
+                    return Err(anyhow!(
+                        "path prefix {:?} of large repo {} would cover the destination prefix \
+                         {:?} already used by another small repo",
+                        dest,
+                        large_repo_id,
+                        covered,
+                    ));
+                }
+            }
+
+            dest_prefixes.extend(this_repo_prefixes);
+        }
+
+        Ok(Self {
+            large_repo_id,
+            direction,
+            common_pushrebase_bookmarks,
+            small_repos,
+            version_name,
+        })
+    }
+}
+
 /// Config that applies to all mapping versions
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct CommonCommitSyncConfig {
@@ -1434,28 +1772,45 @@ impl SourceControlServiceParams {
 
     /// Returns true if the named service is permitted to modify all of the paths
     /// that a bonsai changeset modifies.
-    pub fn service_write_paths_permitted<'cs>(
+    ///
+    /// `manifest_diff` is consulted only when the service has
+    /// `excluded_path_prefixes` configured: a top-level bonsai change can
+    /// wholesale replace a directory with a file (or vice versa), in which
+    /// case `file_changes_map()` won't list the individual paths that used
+    /// to live underneath it, so an exclusion on one of those paths would
+    /// otherwise go unenforced. The happy path with no exclusions never
+    /// touches `manifest_diff` and stays cheap, but once exclusions are
+    /// configured, a missing `manifest_diff` fails closed
+    /// (`ManifestDiffRequired`) rather than silently skipping enforcement.
+    pub fn service_write_paths_permitted(
         &self,
         service_identity: impl AsRef<str>,
-        bonsai: &'cs BonsaiChangeset,
-    ) -> Result<(), &'cs MPath> {
+        bonsai: &BonsaiChangeset,
+        manifest_diff: Option<&dyn ManifestDiff>,
+    ) -> Result<(), ServiceWritePathError> {
         if let Some(restrictions) = self
             .service_write_restrictions
             .get(service_identity.as_ref())
         {
-            // Currently path prefixes are only used to grant permission.
-            // This means we only need to check if all of the bonsai paths
-            // are covered by the prefixes in the configuration.
-            //
-            // In the future, we may want to add exclusions to the paths
-            // (e.g. dir1/ is permitted except for dir1/subdir1/).  When
-            // this happens we'll need to do a manifest diff, as the bonsai
-            // changes won't include dir1/subdir1/ files if dir1 is
-            // replaced by a file.
             let trie = &restrictions.permitted_path_prefixes;
+            let excluded = &restrictions.excluded_path_prefixes;
+            let has_exclusions = *excluded != PrefixTrie::default();
+
             for path in bonsai.file_changes_map().keys() {
-                if !trie.contains_prefix(path) {
-                    return Err(path);
+                if !trie.contains_prefix(path) || excluded.contains_prefix(path) {
+                    return Err(ServiceWritePathError::NotPermitted(path.clone()));
+                }
+
+                if has_exclusions {
+                    let manifest_diff = manifest_diff
+                        .ok_or_else(|| ServiceWritePathError::ManifestDiffRequired(path.clone()))?;
+                    let replaced = manifest_diff
+                        .paths_replaced_under(path)
+                        .map_err(ServiceWritePathError::ManifestDiff)?;
+                    if let Some(offending) = replaced.iter().find(|p| excluded.contains_prefix(p))
+                    {
+                        return Err(ServiceWritePathError::NotPermitted(offending.clone()));
+                    }
                 }
             }
         }
@@ -1463,6 +1818,46 @@ impl SourceControlServiceParams {
     }
 }
 
+/// Materializes the paths that a top-level bonsai change wholesale replaced,
+/// so `service_write_paths_permitted` can enforce `excluded_path_prefixes`
+/// even when `file_changes_map()` doesn't mention them individually. This
+/// crate has no blobstore access of its own, so implementations are
+/// provided by callers that can diff against the parent manifest(s).
+pub trait ManifestDiff {
+    /// Returns every path that existed under `path` in the parent
+    /// manifest(s) but is no longer listed as its own bonsai file change,
+    /// because `path` itself was replaced wholesale by this changeset.
+    fn paths_replaced_under(&self, path: &MPath) -> Result<Vec<MPath>, Error>;
+}
+
+/// Error from `SourceControlServiceParams::service_write_paths_permitted`.
+#[derive(Debug)]
+pub enum ServiceWritePathError {
+    /// The write touches a path the service isn't permitted to modify.
+    NotPermitted(MPath),
+    /// Enforcing an exclusion required diffing against the parent
+    /// manifest(s), and that diff failed.
+    ManifestDiff(Error),
+    /// The service has `excluded_path_prefixes` configured, but the caller
+    /// didn't supply a `manifest_diff` to enforce them against this path, so
+    /// the write is rejected rather than silently let through.
+    ManifestDiffRequired(MPath),
+}
+
+impl fmt::Display for ServiceWritePathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotPermitted(path) => write!(f, "path {} is not permitted", path),
+            Self::ManifestDiff(e) => write!(f, "failed to diff parent manifest: {}", e),
+            Self::ManifestDiffRequired(path) => write!(
+                f,
+                "cannot enforce excluded_path_prefixes for path {} without a manifest_diff",
+                path
+            ),
+        }
+    }
+}
+
 /// Restrictions on writes for services.
 #[derive(Debug, Default, Clone, Eq, PartialEq)]
 pub struct ServiceWriteRestrictions {
@@ -1472,6 +1867,10 @@ pub struct ServiceWriteRestrictions {
     /// The service is permitted to modify files with these path prefixes.
     pub permitted_path_prefixes: PrefixTrie,
 
+    /// Carve-outs within `permitted_path_prefixes` that the service is NOT
+    /// permitted to modify, even though they fall under a permitted prefix.
+    pub excluded_path_prefixes: PrefixTrie,
+
     /// The service is permitted to modify these bookmarks.
     pub permitted_bookmarks: HashSet<String>,
 
@@ -1488,6 +1887,19 @@ pub struct SourceControlServiceMonitoring {
     /// a freshness value may be the `now - author_date` of
     /// the commit, to which the bookmark points
     pub bookmarks_to_report_age: Vec<BookmarkName>,
+
+    /// Regex matching additional bookmarks to report age for, beyond the
+    /// explicit list in `bookmarks_to_report_age`. Useful for naming
+    /// schemes (e.g. release bookmarks) where enumerating every bookmark
+    /// individually would be unwieldy. Defaults to `None`, meaning no
+    /// additional bookmarks are matched.
+    pub bookmark_age_regex: Option<ComparableRegex>,
+
+    /// Per-bookmark staleness threshold: a bookmark whose reported age
+    /// exceeds the configured `Duration` is considered stale for alerting
+    /// purposes. A bookmark with no entry here keeps today's behavior of
+    /// having its age logged with no staleness threshold applied.
+    pub bookmark_max_age: HashMap<BookmarkName, Duration>,
 }
 
 /// Represents the repository name for this repository in Hgsql.
@@ -1580,6 +1992,20 @@ pub struct SegmentedChangelogConfig {
     /// `bonsai_changesets_to_include` then every reseeding would add B and it's
     /// ancestors to the reseeded segmented changelog.
     pub bonsai_changesets_to_include: Vec<ChangesetId>,
+    /// Extra bonsai changesets to include as heads only when the
+    /// background tailer builds/reseeds the Dag, on top of
+    /// `bonsai_changesets_to_include` (which applies to every job). This
+    /// lets the tailer warm a broader Dag for e.g. pre-fetching, without
+    /// bloating what the live server advertises to clients on clone.
+    /// Defaults to empty, which keeps today's behavior where the tailer
+    /// and the live server build/advertise the same heads.
+    pub tailer_only_heads: Vec<ChangesetId>,
+    /// Max number of commits a client clone/pull may traverse when the
+    /// server lacks a precomputed location for the requested commit.
+    /// Defaults to `None`, meaning fall back to a crate-level constant;
+    /// set per-repo so large/busy repos can cap expensive graph walks
+    /// independently of smaller ones.
+    pub client_max_commits_to_traverse: Option<u64>,
 }
 
 impl Default for SegmentedChangelogConfig {
@@ -1592,6 +2018,8 @@ impl Default for SegmentedChangelogConfig {
             reload_dag_save_period: Some(Duration::from_secs(3600)),
             update_to_master_bookmark_period: Some(Duration::from_secs(60)),
             bonsai_changesets_to_include: vec![],
+            tailer_only_heads: vec![],
+            client_max_commits_to_traverse: None,
         }
     }
 }
@@ -0,0 +1,25 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+mod local_cache;
+mod reader;
+mod remote_cache;
+mod remote_cache_fill;
+mod store;
+mod writer;
+
+#[cfg(test)]
+mod test;
+
+pub use crate::local_cache::LocalCache;
+pub use crate::reader::NewFilenodesReader;
+pub use crate::remote_cache::RemoteCache;
+pub use crate::remote_cache_fill::FillKey;
+pub use crate::remote_cache_fill::FillMetrics;
+pub use crate::remote_cache_fill::FillQueue;
+pub use crate::store::Shard;
+pub use crate::writer::NewFilenodesWriter;
@@ -0,0 +1,103 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Process-local cache consulted before the store, so repeat reads for a
+//! path/filenode that was recently served don't round-trip at all.
+
+use filenodes::FilenodeInfo;
+use mercurial_types::HgFileNodeId;
+use mononoke_types::RepoPath;
+
+pub enum LocalCache {
+    Noop,
+    #[cfg(test)]
+    Test(test::HashMapCache),
+}
+
+impl LocalCache {
+    pub fn get_filenode(&self, path: &RepoPath, filenode: HgFileNodeId) -> Option<FilenodeInfo> {
+        match self {
+            LocalCache::Noop => None,
+            #[cfg(test)]
+            LocalCache::Test(cache) => cache.get_filenode(path, filenode),
+        }
+    }
+
+    pub fn get_history(&self, path: &RepoPath) -> Option<Vec<FilenodeInfo>> {
+        match self {
+            LocalCache::Noop => None,
+            #[cfg(test)]
+            LocalCache::Test(cache) => cache.get_history(path),
+        }
+    }
+
+    pub fn fill_filenode(&self, path: &RepoPath, info: &FilenodeInfo) {
+        match self {
+            LocalCache::Noop => {}
+            #[cfg(test)]
+            LocalCache::Test(cache) => cache.fill_filenode(path, info),
+        }
+    }
+
+    pub fn fill_history(&self, path: &RepoPath, infos: &[FilenodeInfo]) {
+        match self {
+            LocalCache::Noop => {}
+            #[cfg(test)]
+            LocalCache::Test(cache) => cache.fill_history(path, infos),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    use super::*;
+
+    #[derive(Default)]
+    pub struct HashMapCache {
+        filenodes: Mutex<HashMap<(RepoPath, HgFileNodeId), FilenodeInfo>>,
+        history: Mutex<HashMap<RepoPath, Vec<FilenodeInfo>>>,
+    }
+
+    impl HashMapCache {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        pub fn get_filenode(&self, path: &RepoPath, filenode: HgFileNodeId) -> Option<FilenodeInfo> {
+            self.filenodes
+                .lock()
+                .expect("HashMapCache poisoned")
+                .get(&(path.clone(), filenode))
+                .cloned()
+        }
+
+        pub fn get_history(&self, path: &RepoPath) -> Option<Vec<FilenodeInfo>> {
+            self.history
+                .lock()
+                .expect("HashMapCache poisoned")
+                .get(path)
+                .cloned()
+        }
+
+        pub fn fill_filenode(&self, path: &RepoPath, info: &FilenodeInfo) {
+            self.filenodes
+                .lock()
+                .expect("HashMapCache poisoned")
+                .insert((path.clone(), info.filenode), info.clone());
+        }
+
+        pub fn fill_history(&self, path: &RepoPath, infos: &[FilenodeInfo]) {
+            self.history
+                .lock()
+                .expect("HashMapCache poisoned")
+                .insert(path.clone(), infos.to_vec());
+        }
+    }
+}
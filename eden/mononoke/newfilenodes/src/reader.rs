@@ -0,0 +1,99 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Reads filenodes through the local cache, then the store, filling both
+//! caches on a miss. The remote-cache fill is enqueued on a `FillQueue`
+//! rather than awaited, so it never adds latency to the read.
+
+use std::sync::Arc;
+
+use anyhow::Error;
+use context::CoreContext;
+use filenodes::FilenodeInfo;
+use mercurial_types::HgFileNodeId;
+use mononoke_types::RepoPath;
+use mononoke_types::RepositoryId;
+
+use crate::local_cache::LocalCache;
+use crate::remote_cache::RemoteCache;
+use crate::remote_cache_fill::FillKey;
+use crate::remote_cache_fill::FillQueue;
+use crate::store::Shard;
+
+pub struct NewFilenodesReader {
+    pub local_cache: LocalCache,
+    pub remote_cache: RemoteCache,
+    fill_queue: FillQueue,
+    store: Arc<Shard>,
+}
+
+impl NewFilenodesReader {
+    pub fn new(store: Arc<Shard>, fill_queue: FillQueue) -> Self {
+        NewFilenodesReader {
+            local_cache: LocalCache::Noop,
+            remote_cache: RemoteCache::Noop,
+            fill_queue,
+            store,
+        }
+    }
+
+    pub async fn get_filenode(
+        &self,
+        _ctx: &CoreContext,
+        repo_id: RepositoryId,
+        path: &RepoPath,
+        filenode: HgFileNodeId,
+    ) -> Result<Option<FilenodeInfo>, Error> {
+        if let Some(info) = self.local_cache.get_filenode(path, filenode) {
+            return Ok(Some(info));
+        }
+
+        let info = self.store.get_filenode(repo_id, path, filenode);
+        if let Some(info) = &info {
+            self.local_cache.fill_filenode(path, info);
+            self.enqueue_remote_filenode_fill(path.clone(), info.clone());
+        }
+
+        Ok(info)
+    }
+
+    pub async fn get_all_filenodes_for_path(
+        &self,
+        _ctx: &CoreContext,
+        repo_id: RepositoryId,
+        path: &RepoPath,
+    ) -> Result<Vec<FilenodeInfo>, Error> {
+        if let Some(infos) = self.local_cache.get_history(path) {
+            return Ok(infos);
+        }
+
+        let infos = self.store.get_history(repo_id, path);
+        if !infos.is_empty() {
+            self.local_cache.fill_history(path, &infos);
+            self.enqueue_remote_history_fill(path.clone(), infos.clone());
+        }
+
+        Ok(infos)
+    }
+
+    /// Off the hot path: the remote cache is filled by a background
+    /// `FillQueue` worker, deduplicated against any fill already in flight
+    /// for this `(path, filenode)`.
+    fn enqueue_remote_filenode_fill(&self, path: RepoPath, info: FilenodeInfo) {
+        let remote_cache = self.remote_cache.clone();
+        let key = FillKey::Filenode(path.clone(), info.filenode);
+        self.fill_queue
+            .enqueue(key, async move { remote_cache.fill_filenode(&path, &info).await });
+    }
+
+    fn enqueue_remote_history_fill(&self, path: RepoPath, infos: Vec<FilenodeInfo>) {
+        let remote_cache = self.remote_cache.clone();
+        let key = FillKey::History(path.clone());
+        self.fill_queue
+            .enqueue(key, async move { remote_cache.fill_history(&path, &infos).await });
+    }
+}
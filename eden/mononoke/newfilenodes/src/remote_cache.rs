@@ -0,0 +1,129 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! The shared, cross-host remote cache. Fills are driven off the hot path
+//! by `remote_cache_fill::FillQueue`; see `reader.rs`.
+
+use anyhow::Error;
+use filenodes::FilenodeInfo;
+use mercurial_types::HgFileNodeId;
+use mononoke_types::RepoPath;
+
+#[derive(Clone)]
+pub enum RemoteCache {
+    Noop,
+    #[cfg(test)]
+    Test(std::sync::Arc<test::TestCache>),
+}
+
+impl RemoteCache {
+    pub async fn fill_filenode(&self, path: &RepoPath, info: &FilenodeInfo) -> Result<(), Error> {
+        match self {
+            RemoteCache::Noop => Ok(()),
+            #[cfg(test)]
+            RemoteCache::Test(cache) => {
+                cache.fill_filenode(path.clone(), info.clone());
+                Ok(())
+            }
+        }
+    }
+
+    pub async fn fill_history(&self, path: &RepoPath, infos: &[FilenodeInfo]) -> Result<(), Error> {
+        match self {
+            RemoteCache::Noop => Ok(()),
+            #[cfg(test)]
+            RemoteCache::Test(cache) => {
+                cache.fill_history(path.clone());
+                let _ = infos;
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use std::collections::HashSet;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    use anyhow::anyhow;
+    use anyhow::Error;
+    use filenodes::FilenodeInfo;
+    use mercurial_types::HgFileNodeId;
+    use mononoke_types::RepoPath;
+
+    use super::RemoteCache;
+
+    #[derive(Default)]
+    pub struct TestCache {
+        filenodes: Mutex<HashSet<(RepoPath, HgFileNodeId)>>,
+        history: Mutex<HashSet<RepoPath>>,
+    }
+
+    impl TestCache {
+        pub(super) fn fill_filenode(&self, path: RepoPath, info: FilenodeInfo) {
+            self.filenodes
+                .lock()
+                .expect("TestCache poisoned")
+                .insert((path, info.filenode));
+        }
+
+        pub(super) fn fill_history(&self, path: RepoPath) {
+            self.history.lock().expect("TestCache poisoned").insert(path);
+        }
+
+        fn has_filenode(&self, path: &RepoPath, filenode: HgFileNodeId) -> bool {
+            self.filenodes
+                .lock()
+                .expect("TestCache poisoned")
+                .contains(&(path.clone(), filenode))
+        }
+
+        fn has_history(&self, path: &RepoPath) -> bool {
+            self.history.lock().expect("TestCache poisoned").contains(path)
+        }
+    }
+
+    pub fn make_test_cache() -> RemoteCache {
+        RemoteCache::Test(Arc::new(TestCache::default()))
+    }
+
+    /// Poll until `path`/`filenode` has been filled into `cache`. The fill
+    /// now happens on a background `FillQueue` worker rather than inline, so
+    /// callers can no longer assume it has landed the instant the read that
+    /// triggered it returns.
+    pub async fn wait_for_filenode(
+        cache: &RemoteCache,
+        path: &RepoPath,
+        filenode: HgFileNodeId,
+    ) -> Result<(), Error> {
+        if let RemoteCache::Test(test_cache) = cache {
+            for _ in 0..200 {
+                if test_cache.has_filenode(path, filenode) {
+                    return Ok(());
+                }
+                tokio_preview::time::delay_for(Duration::from_millis(5)).await;
+            }
+        }
+        Err(anyhow!("filenode was never filled into the remote cache"))
+    }
+
+    /// Poll until `path`'s history has been filled into `cache`.
+    pub async fn wait_for_history(cache: &RemoteCache, path: &RepoPath) -> Result<(), Error> {
+        if let RemoteCache::Test(test_cache) = cache {
+            for _ in 0..200 {
+                if test_cache.has_history(path) {
+                    return Ok(());
+                }
+                tokio_preview::time::delay_for(Duration::from_millis(5)).await;
+            }
+        }
+        Err(anyhow!("history was never filled into the remote cache"))
+    }
+}
@@ -0,0 +1,64 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! A single filenodes shard: durable storage that `NewFilenodesReader` and
+//! `NewFilenodesWriter` share, beneath the local/remote caches.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use filenodes::FilenodeInfo;
+use mercurial_types::HgFileNodeId;
+use mononoke_types::RepoPath;
+use mononoke_types::RepositoryId;
+
+#[derive(Default)]
+pub struct Shard {
+    filenodes: Mutex<HashMap<(RepositoryId, RepoPath, HgFileNodeId), FilenodeInfo>>,
+    history: Mutex<HashMap<(RepositoryId, RepoPath), Vec<FilenodeInfo>>>,
+}
+
+impl Shard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&self, repo_id: RepositoryId, path: RepoPath, info: FilenodeInfo) {
+        self.filenodes
+            .lock()
+            .expect("Shard poisoned")
+            .insert((repo_id, path.clone(), info.filenode), info.clone());
+        self.history
+            .lock()
+            .expect("Shard poisoned")
+            .entry((repo_id, path))
+            .or_insert_with(Vec::new)
+            .push(info);
+    }
+
+    pub fn get_filenode(
+        &self,
+        repo_id: RepositoryId,
+        path: &RepoPath,
+        filenode: HgFileNodeId,
+    ) -> Option<FilenodeInfo> {
+        self.filenodes
+            .lock()
+            .expect("Shard poisoned")
+            .get(&(repo_id, path.clone(), filenode))
+            .cloned()
+    }
+
+    pub fn get_history(&self, repo_id: RepositoryId, path: &RepoPath) -> Vec<FilenodeInfo> {
+        self.history
+            .lock()
+            .expect("Shard poisoned")
+            .get(&(repo_id, path.clone()))
+            .cloned()
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,33 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::sync::Arc;
+
+use anyhow::Error;
+
+use crate::reader::NewFilenodesReader;
+use crate::remote_cache_fill::FillQueue;
+use crate::store::Shard;
+use crate::writer::NewFilenodesWriter;
+
+pub(crate) fn build_shard() -> Result<Shard, Error> {
+    Ok(Shard::new())
+}
+
+pub(crate) fn build_reader_writer(shards: Vec<Shard>) -> (NewFilenodesReader, NewFilenodesWriter) {
+    let store = Arc::new(
+        shards
+            .into_iter()
+            .next()
+            .expect("build_reader_writer needs at least one shard"),
+    );
+    let (fill_queue, _worker_handles) = FillQueue::spawn(1, 16);
+    (
+        NewFilenodesReader::new(Arc::clone(&store), fill_queue),
+        NewFilenodesWriter::new(store),
+    )
+}
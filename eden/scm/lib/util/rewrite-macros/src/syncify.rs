@@ -9,15 +9,12 @@ extern crate proc_macro;
 
 use crate::prelude::*;
 
-pub(crate) fn syncify(attr: TokenStream, mut tokens: TokenStream) -> TokenStream {
+pub(crate) fn syncify(attr: TokenStream, tokens: TokenStream) -> TokenStream {
     let debug = !attr.find_all(parse("debug")).is_empty();
-    tokens
-        .replace_all(parse(".await"), parse(""))
-        .replace_all(parse(".boxed()"), parse(""))
-        .replace_all(parse("async move"), parse(""))
-        .replace_all(parse("async"), parse(""))
-        .replace_all(parse("#[tokio::test]"), parse("#[test]"))
-        .replace_all(parse("__::block_on(___g1)"), parse("___g1"));
+    let dual_suffix = dual_suffix(&attr);
+
+    let mut sync_tokens = tokens.clone();
+    rewrite_to_sync(&mut sync_tokens);
 
     // Apply customized replaces.
     let matches = attr.find_all(parse("[___g1] => [___g2]"));
@@ -27,13 +24,128 @@ pub(crate) fn syncify(attr: TokenStream, mut tokens: TokenStream) -> TokenStream
     for m in matches {
         let pat = m.captures.get("___g1").unwrap();
         let replace = m.captures.get("___g2").unwrap();
-        tokens.replace_all_raw(pat, replace);
+        sync_tokens.replace_all_raw(pat, replace);
     }
 
     // `cargo expand` can also be used to produce output.
     if debug {
-        eprintln!("output: [[[\n{}\n]]]", unparse(&tokens));
+        eprintln!("output: [[[\n{}\n]]]", unparse(&sync_tokens));
+    }
+
+    match dual_suffix {
+        Some(suffix) => {
+            let twins = rename_fns(&mut sync_tokens, &suffix);
+            if debug {
+                eprintln!("dual: generated {} sync twin(s) with suffix {:?}", twins, suffix);
+                eprintln!("twin: [[[\n{}\n]]]", unparse(&sync_tokens));
+            }
+            // Keep the original async item untouched and emit the rewritten
+            // sync twin alongside it, rather than replacing it.
+            let mut out = tokens;
+            out.extend(sync_tokens);
+            out
+        }
+        None => sync_tokens,
+    }
+}
+
+/// Parse the `dual` (default suffix `_sync`) or `dual(___suffix)` attribute
+/// form, returning the suffix to use for the generated sync twin, or `None`
+/// if dual emission wasn't requested.
+fn dual_suffix(attr: &TokenStream) -> Option<String> {
+    if let Some(m) = attr.find_all(parse("dual(___suffix)")).into_iter().next() {
+        let suffix = m.captures.get("___suffix").unwrap();
+        return Some(unparse(suffix).trim_matches('"').to_string());
     }
+    if !attr.find_all(parse("dual")).is_empty() {
+        return Some("_sync".to_string());
+    }
+    None
+}
 
+/// Destructively rewrite an async item into its sync equivalent: the same
+/// transform `syncify` has always applied, now also covering `try_collect`
+/// and `join!`/`try_join!`, which show up often in bookmark/store code.
+///
+/// `try_join!` needs its `?` applied per-argument rather than to the whole
+/// tuple: `try_join!(a, b)` becomes `(a, b)` awaited, and a tuple doesn't
+/// implement `Try`, so `(a, b)?` doesn't compile. The arm below matches
+/// common arities (2-4 futures) before falling back to the single-future
+/// case, since `replace_all` tries patterns in the order they're chained.
+fn rewrite_to_sync(tokens: &mut TokenStream) {
     tokens
+        .replace_all(parse(".await"), parse(""))
+        .replace_all(parse(".boxed()"), parse(""))
+        .replace_all(parse("async move"), parse(""))
+        .replace_all(parse("async"), parse(""))
+        .replace_all(parse("#[tokio::test]"), parse("#[test]"))
+        .replace_all(parse("__::block_on(___g1)"), parse("___g1"))
+        .replace_all(
+            parse("___g1.try_collect::<Vec<___g2>>()"),
+            parse("___g1.collect::<Result<Vec<___g2>, _>>()"),
+        )
+        .replace_all(parse("__::join!(___g1)"), parse("(___g1)"))
+        .replace_all(parse("join!(___g1)"), parse("(___g1)"))
+        .replace_all(
+            parse("__::try_join!(___g1, ___g2, ___g3, ___g4)"),
+            parse("(___g1?, ___g2?, ___g3?, ___g4?)"),
+        )
+        .replace_all(
+            parse("try_join!(___g1, ___g2, ___g3, ___g4)"),
+            parse("(___g1?, ___g2?, ___g3?, ___g4?)"),
+        )
+        .replace_all(
+            parse("__::try_join!(___g1, ___g2, ___g3)"),
+            parse("(___g1?, ___g2?, ___g3?)"),
+        )
+        .replace_all(
+            parse("try_join!(___g1, ___g2, ___g3)"),
+            parse("(___g1?, ___g2?, ___g3?)"),
+        )
+        .replace_all(
+            parse("__::try_join!(___g1, ___g2)"),
+            parse("(___g1?, ___g2?)"),
+        )
+        .replace_all(parse("try_join!(___g1, ___g2)"), parse("(___g1?, ___g2?)"))
+        .replace_all(parse("__::try_join!(___g1)"), parse("(___g1?)"))
+        .replace_all(parse("try_join!(___g1)"), parse("(___g1?)"));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn try_join_applies_try_per_argument() {
+        let mut tokens = parse("try_join!(a, b)");
+        rewrite_to_sync(&mut tokens);
+        assert_eq!(unparse(&tokens), unparse(&parse("(a?, b?)")));
+    }
+
+    #[test]
+    fn try_join_single_argument() {
+        let mut tokens = parse("try_join!(a)");
+        rewrite_to_sync(&mut tokens);
+        assert_eq!(unparse(&tokens), unparse(&parse("(a?)")));
+    }
+
+    #[test]
+    fn join_is_left_untouched_by_try_join_rewrite() {
+        let mut tokens = parse("join!(a, b)");
+        rewrite_to_sync(&mut tokens);
+        assert_eq!(unparse(&tokens), unparse(&parse("(a, b)")));
+    }
+}
+
+/// Append `suffix` to the name of every top-level `fn` in `tokens`, in
+/// place. Returns the number of functions renamed, for `debug` reporting.
+fn rename_fns(tokens: &mut TokenStream, suffix: &str) -> usize {
+    let matches = tokens.find_all(parse("fn ___name ("));
+    let count = matches.len();
+    for m in matches {
+        let name = m.captures.get("___name").unwrap();
+        let renamed = parse(&format!("{}{}", unparse(name), suffix));
+        tokens.replace_all_raw(name, &renamed);
+    }
+    count
 }
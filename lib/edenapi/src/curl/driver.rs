@@ -1,6 +1,17 @@
 // Copyright Facebook, Inc. 2019
 
-use std::{fmt::Write, mem, time::Duration};
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::Write,
+    mem,
+    os::raw::c_long,
+    sync::{
+        mpsc::{self, Receiver, Sender, TryRecvError},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
 
 use curl::{
     self,
@@ -8,6 +19,7 @@ use curl::{
     multi::{Easy2Handle, Multi},
 };
 use failure::{err_msg, Fallible};
+use rand::Rng;
 
 use crate::progress::ProgressManager;
 
@@ -15,15 +27,102 @@ use crate::progress::ProgressManager;
 /// on any active transfer in a curl::Multi session.
 const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
 
+/// Base delay for the first retry of a failed transfer; subsequent retries
+/// back off exponentially from this value.
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+
+/// A predicate deciding whether a failed transfer is worth retrying, given
+/// the curl error and the HTTP status code (if the transfer completed with
+/// one).
+pub type RetryPredicate = Arc<dyn Fn(&curl::Error, Option<u32>) -> bool + Send + Sync>;
+
+/// The default retry predicate: retries connection/timeout/IO-level curl
+/// errors as well as any 5xx HTTP response.
+fn default_retryable(e: &curl::Error, status: Option<u32>) -> bool {
+    if let Some(status) = status {
+        if status >= 500 {
+            return true;
+        }
+    }
+
+    e.is_couldnt_connect()
+        || e.is_operation_timedout()
+        || e.is_recv_error()
+        || e.is_send_error()
+        || e.is_partial_file()
+}
+
+/// Identifies a single transfer within a `MultiDriver` session. Assigned
+/// in insertion order by `MultiDriver::add`.
+pub type Token = usize;
+
+/// Live state of a single transfer, as reported by `MultiDriverHandle::status`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TransferState {
+    /// The transfer has not started yet.
+    Idle,
+    /// The transfer is active; `bytes` is the number of bytes downloaded so far.
+    InProgress {
+        /// Bytes downloaded so far.
+        bytes: u64,
+    },
+    /// The transfer has been paused via `MultiDriverCommand::Pause` and is
+    /// not counted against the Multi session.
+    Paused,
+    /// The transfer failed but is retryable; it is waiting out a backoff
+    /// delay before being re-added to the Multi session.
+    Sleeping {
+        /// Number of attempts made so far, including the one that failed.
+        attempt: u32,
+    },
+    /// The transfer completed successfully.
+    Completed,
+    /// The transfer failed with the given curl error code.
+    Failed(u32),
+    /// The transfer was cancelled via `MultiDriverCommand::Cancel`/`CancelAll`.
+    Cancelled,
+}
+
+/// A command sent to a running `MultiDriver` session to steer a transfer
+/// while it is in flight.
+pub enum MultiDriverCommand {
+    /// Pause the transfer with the given token.
+    Pause(Token),
+    /// Resume a previously paused transfer.
+    Resume(Token),
+    /// Cancel the transfer with the given token immediately; it is removed
+    /// from the `Multi` session and reported as cancelled rather than failed.
+    Cancel(Token),
+    /// Cancel every transfer that is still in flight.
+    CancelAll,
+}
+
 /// The result of using a MultiDriver to manage a curl::Multi session.
 /// Contains all of the Easy2 handles for the session along with
-/// information about which (if any) of the transfers failed.
+/// information about which (if any) of the transfers failed or were
+/// cancelled.
 pub struct MultiDriverResult<H> {
     handles: Vec<Easy2<H>>,
-    failed: Vec<(usize, curl::Error)>,
+    failed: Vec<(Token, curl::Error)>,
+    cancelled: Vec<Token>,
+    retries: HashMap<Token, u32>,
 }
 
 impl<H> MultiDriverResult<H> {
+    /// Tokens of transfers that were cancelled via a `MultiDriverCommand`.
+    pub fn cancelled(&self) -> &[Token] {
+        &self.cancelled
+    }
+
+    /// Number of retry attempts made for each transfer that was retried at
+    /// least once, whether or not it eventually succeeded. Transfers that
+    /// were never retried (because they succeeded on the first attempt,
+    /// retrying was disabled, or their failure was not retryable) are
+    /// absent from the map.
+    pub fn retries(&self) -> &HashMap<Token, u32> {
+        &self.retries
+    }
+
     pub fn into_result(self) -> Fallible<Vec<Easy2<H>>> {
         if self.failed.is_empty() {
             return Ok(self.handles);
@@ -38,11 +137,129 @@ impl<H> MultiDriverResult<H> {
     }
 }
 
-/// Struct that manages a curl::Multi session, synchronously driving
-/// all of the transfers therein to completion.
+/// A single transfer's outcome, emitted as soon as it happens rather than
+/// waiting for the whole batch to drain. See `MultiDriver::events`.
+pub struct TransferEvent {
+    pub token: Token,
+    /// `Ok(())` on success; `Err(code)` with the curl error code on a
+    /// terminal failure (after retries, if any, are exhausted).
+    pub result: Result<(), u32>,
+    /// Bytes downloaded by this transfer when it finished.
+    pub bytes: u64,
+}
+
+/// One transfer's slot inside a running session: either still registered
+/// with the `Multi` handle, paused (removed from `Multi` but kept around so
+/// it can be re-added later), or already removed for good (cancelled or
+/// finished).
+enum Slot<H> {
+    Active(Easy2Handle<H>),
+    Paused(Easy2<H>),
+    /// Not yet admitted to the `Multi` session because `max_concurrent` was
+    /// reached when it was added; its token sits in the `pending` queue.
+    Pending(Easy2<H>),
+    /// Failed but retryable; waiting out a backoff delay before being
+    /// re-queued for admission.
+    Sleeping { easy: Easy2<H>, ready_at: Instant },
+    /// Finished for good: succeeded, exhausted its retries, or was
+    /// cancelled. Kept around so `remove_all` can still return it.
+    Finished(Easy2<H>),
+    /// Transient placeholder used while a slot is being moved between the
+    /// states above; never observed outside of a single transition.
+    Removed,
+}
+
+/// A handle to a `MultiDriver` session running on a background thread.
+///
+/// Dropping the handle does not stop the session; use `cancel_all` and
+/// `join` to tear it down cleanly.
+pub struct MultiDriverHandle<H> {
+    commands: Sender<MultiDriverCommand>,
+    status: Arc<Mutex<HashMap<Token, TransferState>>>,
+    worker: Option<thread::JoinHandle<Fallible<MultiDriverResult<H>>>>,
+}
+
+impl<H: Handler + Send + 'static> MultiDriverHandle<H> {
+    /// Pause the given transfer.
+    pub fn pause(&self, token: Token) -> Fallible<()> {
+        self.commands
+            .send(MultiDriverCommand::Pause(token))
+            .map_err(|_| err_msg("MultiDriver worker thread is no longer running"))
+    }
+
+    /// Resume a previously paused transfer.
+    pub fn resume(&self, token: Token) -> Fallible<()> {
+        self.commands
+            .send(MultiDriverCommand::Resume(token))
+            .map_err(|_| err_msg("MultiDriver worker thread is no longer running"))
+    }
+
+    /// Cancel the given transfer.
+    pub fn cancel(&self, token: Token) -> Fallible<()> {
+        self.commands
+            .send(MultiDriverCommand::Cancel(token))
+            .map_err(|_| err_msg("MultiDriver worker thread is no longer running"))
+    }
+
+    /// Cancel every transfer still in flight.
+    pub fn cancel_all(&self) -> Fallible<()> {
+        self.commands
+            .send(MultiDriverCommand::CancelAll)
+            .map_err(|_| err_msg("MultiDriver worker thread is no longer running"))
+    }
+
+    /// Snapshot of the current state of a single transfer.
+    pub fn status(&self, token: Token) -> Option<TransferState> {
+        self.status.lock().unwrap().get(&token).cloned()
+    }
+
+    /// Snapshot of the current state of every transfer in the session.
+    pub fn status_all(&self) -> HashMap<Token, TransferState> {
+        self.status.lock().unwrap().clone()
+    }
+
+    /// Block until the session has driven every transfer to completion
+    /// (or until it has been cancelled) and return the final result.
+    pub fn join(mut self) -> Fallible<MultiDriverResult<H>> {
+        match self.worker.take() {
+            Some(worker) => worker
+                .join()
+                .map_err(|_| err_msg("MultiDriver worker thread panicked"))?,
+            None => Err(err_msg("MultiDriver session has already been joined")),
+        }
+    }
+}
+
+/// Struct that manages a curl::Multi session, driving all of the transfers
+/// therein to completion on a background thread while allowing the caller
+/// to pause, resume, or cancel individual transfers and query their status.
 pub struct MultiDriver<H> {
     multi: Multi,
-    handles: Vec<Easy2Handle<H>>,
+    slots: Vec<Slot<H>>,
+    /// Tokens of transfers waiting to be admitted to the `Multi` session,
+    /// in the order they should be admitted.
+    pending: VecDeque<Token>,
+    /// Maximum number of transfers allowed to be live in the `Multi`
+    /// session at once. `None` means unbounded (the historical behavior).
+    max_concurrent: Option<usize>,
+    /// Maximum number of retry attempts for a retryable failure. `None`
+    /// (the default) disables retrying entirely.
+    max_retries: Option<u32>,
+    /// Decides whether a given failure is worth retrying. Defaults to
+    /// `default_retryable`.
+    retryable: RetryPredicate,
+    /// Extracts the number of response bytes already buffered by a
+    /// `Handler`, so a retry can resume via a `Range` header instead of
+    /// re-fetching bytes already received. `None` means retries always
+    /// restart the transfer from the beginning.
+    range_resume: Option<Arc<dyn Fn(&H) -> u64 + Send + Sync>>,
+    /// Number of retries scheduled so far for each transfer that has been
+    /// retried at least once.
+    attempts: HashMap<Token, u32>,
+    /// If set, a `TransferEvent` is sent here as soon as each transfer
+    /// reaches a terminal outcome, rather than waiting for the whole batch
+    /// to finish. See `events`.
+    events: Option<Sender<TransferEvent>>,
     progress: Option<ProgressManager>,
     fail_early: bool,
 }
@@ -51,7 +268,14 @@ impl<H: Handler> MultiDriver<H> {
     pub fn with_capacity(capacity: usize) -> Self {
         Self {
             multi: Multi::new(),
-            handles: Vec::with_capacity(capacity),
+            slots: Vec::with_capacity(capacity),
+            pending: VecDeque::new(),
+            max_concurrent: None,
+            max_retries: None,
+            retryable: Arc::new(default_retryable),
+            range_resume: None,
+            attempts: HashMap::new(),
+            events: None,
             progress: None,
             fail_early: false,
         }
@@ -65,25 +289,140 @@ impl<H: Handler> MultiDriver<H> {
         self.progress.as_ref()
     }
 
-    /// Add an Easy2 handle to the Multi stack.
+    /// Limit the number of transfers that may be live in the `Multi` session
+    /// at once; any handles added beyond the limit are queued and admitted
+    /// as earlier transfers finish. Prevents opening thousands of
+    /// simultaneous sockets when batching a large set of fetches.
+    pub fn set_max_concurrent(&mut self, max_concurrent: usize) {
+        self.max_concurrent = Some(max_concurrent);
+    }
+
+    /// Opt into automatically retrying retryable failures up to `n` times,
+    /// with exponential backoff between attempts.
+    pub fn set_max_retries(&mut self, max_retries: u32) {
+        self.max_retries = Some(max_retries);
+    }
+
+    /// Override the predicate used to decide whether a failure should be
+    /// retried. Defaults to retrying connection/timeout/IO curl errors and
+    /// any 5xx HTTP response.
+    pub fn set_retryable<F>(&mut self, retryable: F)
+    where
+        F: Fn(&curl::Error, Option<u32>) -> bool + Send + Sync + 'static,
+    {
+        self.retryable = Arc::new(retryable);
+    }
+
+    /// Teach the driver how to read the number of response bytes a
+    /// `Handler` has already buffered, so that a retried transfer can send
+    /// a `Range: bytes=<received>-` header and resume rather than
+    /// re-fetching bytes the server already sent.
+    pub fn set_range_resume<F>(&mut self, range_resume: F)
+    where
+        F: Fn(&H) -> u64 + Send + Sync + 'static,
+    {
+        self.range_resume = Some(Arc::new(range_resume));
+    }
+
+    /// Stream completion events instead of (or in addition to) waiting for
+    /// `join`'s terminal `MultiDriverResult`: returns a receiver that yields
+    /// a `TransferEvent` for each transfer as soon as it succeeds or fails
+    /// for good, so a consumer can start processing finished transfers
+    /// while the rest of the batch is still in flight. Must be called
+    /// before `spawn`.
+    pub fn events(&mut self) -> Receiver<TransferEvent> {
+        let (tx, rx) = mpsc::channel();
+        self.events = Some(tx);
+        rx
+    }
+
+    /// Number of transfers currently registered with the `Multi` session.
+    fn active_count(&self) -> usize {
+        self.slots
+            .iter()
+            .filter(|s| matches!(s, Slot::Active(_)))
+            .count()
+    }
+
+    /// Number of transfers parked by `do_pause` and not yet resumed. These
+    /// aren't registered with the `Multi` session and never complete or
+    /// sleep on their own, so the run loop must not treat the session as
+    /// done while any of these remain outstanding.
+    fn paused_count(&self) -> usize {
+        self.slots
+            .iter()
+            .filter(|s| matches!(s, Slot::Paused(_)))
+            .count()
+    }
+
+    /// Add an Easy2 handle to the driver. If a `max_concurrent` limit is set
+    /// and already reached, the handle is queued and admitted to the
+    /// `Multi` stack later, once room opens up; otherwise it is added
+    /// immediately. Either way, the handle keeps the token it is assigned
+    /// here (its index in insertion order) for the lifetime of the session,
+    /// so `MultiDriverResult` always reports transfers in insertion order
+    /// regardless of admission order.
     pub fn add(&mut self, easy: Easy2<H>) -> Fallible<()> {
         // Assign a token to this Easy2 handle so we can correlate messages
         // for this handle with the corresponding Easy2Handle while the
         // Easy2 is owned by the Multi handle.
-        let token = self.handles.len();
+        let token = self.slots.len();
+
+        if let Some(max_concurrent) = self.max_concurrent {
+            if self.active_count() >= max_concurrent {
+                self.slots.push(Slot::Pending(easy));
+                self.pending.push_back(token);
+                return Ok(());
+            }
+        }
+
         let mut handle = self.multi.add2(easy)?;
         handle.set_token(token)?;
-        self.handles.push(handle);
+        self.slots.push(Slot::Active(handle));
         Ok(())
     }
 
+    /// Admit queued handles until either the pending queue is drained or
+    /// the `max_concurrent` window is full again.
+    fn admit_pending(&mut self) -> Fallible<()> {
+        loop {
+            if let Some(max_concurrent) = self.max_concurrent {
+                if self.active_count() >= max_concurrent {
+                    return Ok(());
+                }
+            }
+
+            let token = match self.pending.pop_front() {
+                Some(token) => token,
+                None => return Ok(()),
+            };
+
+            // The handle may have been cancelled while still pending.
+            if let Some(slot @ Slot::Pending(_)) = self.slots.get_mut(token) {
+                let easy = match mem::replace(slot, Slot::Removed) {
+                    Slot::Pending(easy) => easy,
+                    _ => unreachable!(),
+                };
+                let mut handle = self.multi.add2(easy)?;
+                handle.set_token(token)?;
+                *slot = Slot::Active(handle);
+            }
+        }
+    }
+
     /// Remove and return all of the Easy2 handles in the Multi stack.
     pub fn remove_all(&mut self) -> Fallible<Vec<Easy2<H>>> {
-        let handles = mem::replace(&mut self.handles, Vec::with_capacity(0));
-        let mut easy_vec = Vec::with_capacity(handles.len());
-        for handle in handles {
-            let easy = self.multi.remove2(handle)?;
-            easy_vec.push(easy);
+        let slots = mem::replace(&mut self.slots, Vec::with_capacity(0));
+        let mut easy_vec = Vec::with_capacity(slots.len());
+        for slot in slots {
+            match slot {
+                Slot::Active(handle) => easy_vec.push(self.multi.remove2(handle)?),
+                Slot::Paused(easy) | Slot::Pending(easy) | Slot::Finished(easy) => {
+                    easy_vec.push(easy)
+                }
+                Slot::Sleeping { easy, .. } => easy_vec.push(easy),
+                Slot::Removed => {}
+            }
         }
         Ok(easy_vec)
     }
@@ -95,31 +434,247 @@ impl<H: Handler> MultiDriver<H> {
     pub fn fail_early(&mut self, fail_early: bool) {
         self.fail_early = fail_early;
     }
+}
 
-    /// Drive all of the Easy2 handles in the Multi stack to completion.
+impl<H: Handler + Send + 'static> MultiDriver<H> {
+    /// Spawn the `Multi` loop on its own thread and return a handle that can
+    /// be used to pause, resume, or cancel individual transfers and to query
+    /// their live status while the session runs to completion in the
+    /// background.
+    pub(super) fn spawn(mut self) -> MultiDriverHandle<H> {
+        let (commands_tx, commands_rx) = mpsc::channel();
+        let status = Arc::new(Mutex::new(HashMap::with_capacity(self.slots.len())));
+        let worker_status = Arc::clone(&status);
+
+        let worker = thread::spawn(move || self.run(commands_rx, worker_status));
+
+        MultiDriverHandle {
+            commands: commands_tx,
+            status,
+            worker: Some(worker),
+        }
+    }
+
+    /// Pause a single active transfer by removing it from the `Multi`
+    /// session and stashing the underlying `Easy2` so it can be re-added
+    /// by `do_resume`.
+    fn do_pause(&mut self, token: Token) -> Fallible<bool> {
+        match self.slots.get_mut(token) {
+            Some(slot @ Slot::Active(_)) => {
+                let handle = match mem::replace(slot, Slot::Removed) {
+                    Slot::Active(handle) => handle,
+                    _ => unreachable!(),
+                };
+                let easy = self.multi.remove2(handle)?;
+                *slot = Slot::Paused(easy);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Resume a paused transfer by re-adding it to the `Multi` session,
+    /// restoring its original token.
+    fn do_resume(&mut self, token: Token) -> Fallible<bool> {
+        match self.slots.get_mut(token) {
+            Some(slot @ Slot::Paused(_)) => {
+                let easy = match mem::replace(slot, Slot::Removed) {
+                    Slot::Paused(easy) => easy,
+                    _ => unreachable!(),
+                };
+                let mut handle = self.multi.add2(easy)?;
+                handle.set_token(token)?;
+                *slot = Slot::Active(handle);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Cancel a single transfer (active, paused, pending, or sleeping out a
+    /// retry backoff), removing it from the `Multi` session (if present)
+    /// immediately. The underlying `Easy2` is kept so `remove_all` can
+    /// still return it to the caller.
+    fn do_cancel(&mut self, token: Token) -> Fallible<bool> {
+        match self.slots.get_mut(token) {
+            Some(slot @ Slot::Active(_)) => {
+                let handle = match mem::replace(slot, Slot::Removed) {
+                    Slot::Active(handle) => handle,
+                    _ => unreachable!(),
+                };
+                let easy = self.multi.remove2(handle)?;
+                *slot = Slot::Finished(easy);
+                Ok(true)
+            }
+            Some(slot @ Slot::Paused(_)) | Some(slot @ Slot::Pending(_)) => {
+                let easy = match mem::replace(slot, Slot::Removed) {
+                    Slot::Paused(easy) | Slot::Pending(easy) => easy,
+                    _ => unreachable!(),
+                };
+                *slot = Slot::Finished(easy);
+                Ok(true)
+            }
+            Some(slot @ Slot::Sleeping { .. }) => {
+                let easy = match mem::replace(slot, Slot::Removed) {
+                    Slot::Sleeping { easy, .. } => easy,
+                    _ => unreachable!(),
+                };
+                *slot = Slot::Finished(easy);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Apply every command currently queued on `commands` without blocking.
+    fn drain_commands(
+        &mut self,
+        commands: &Receiver<MultiDriverCommand>,
+        cancelled: &mut Vec<Token>,
+        status: &Arc<Mutex<HashMap<Token, TransferState>>>,
+    ) -> Fallible<()> {
+        loop {
+            let command = match commands.try_recv() {
+                Ok(command) => command,
+                Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => return Ok(()),
+            };
+
+            match command {
+                MultiDriverCommand::Pause(token) => {
+                    if self.do_pause(token)? {
+                        status.lock().unwrap().insert(token, TransferState::Paused);
+                    }
+                }
+                MultiDriverCommand::Resume(token) => {
+                    self.do_resume(token)?;
+                }
+                MultiDriverCommand::Cancel(token) => {
+                    if self.do_cancel(token)? {
+                        cancelled.push(token);
+                        status.lock().unwrap().insert(token, TransferState::Cancelled);
+                    }
+                }
+                MultiDriverCommand::CancelAll => {
+                    for token in 0..self.slots.len() {
+                        if self.do_cancel(token)? {
+                            cancelled.push(token);
+                            status.lock().unwrap().insert(token, TransferState::Cancelled);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Number of bytes downloaded so far for an active transfer.
+    fn bytes_downloaded(handle: &Easy2Handle<H>) -> u64 {
+        let mut bytes: curl_sys::curl_off_t = 0;
+        unsafe {
+            curl_sys::curl_easy_getinfo(handle.raw(), curl_sys::CURLINFO_SIZE_DOWNLOAD_T, &mut bytes);
+        }
+        bytes.max(0) as u64
+    }
+
+    /// The HTTP response code for a transfer, if one was received.
+    fn response_code(handle: &Easy2Handle<H>) -> Option<u32> {
+        let mut code: c_long = 0;
+        let rc = unsafe {
+            curl_sys::curl_easy_getinfo(handle.raw(), curl_sys::CURLINFO_RESPONSE_CODE, &mut code)
+        };
+        if rc == curl_sys::CURLE_OK && code > 0 {
+            Some(code as u32)
+        } else {
+            None
+        }
+    }
+
+    /// Promote any transfers whose retry backoff has elapsed back into the
+    /// pending queue, so `admit_pending` re-adds them to the Multi session
+    /// (subject to the `max_concurrent` window like any other handle).
+    fn wake_sleeping(&mut self) {
+        let now = Instant::now();
+        let ready: Vec<Token> = self
+            .slots
+            .iter()
+            .enumerate()
+            .filter_map(|(token, slot)| match slot {
+                Slot::Sleeping { ready_at, .. } if *ready_at <= now => Some(token),
+                _ => None,
+            })
+            .collect();
+
+        for token in ready {
+            if let Some(slot @ Slot::Sleeping { .. }) = self.slots.get_mut(token) {
+                let easy = match mem::replace(slot, Slot::Removed) {
+                    Slot::Sleeping { easy, .. } => easy,
+                    _ => unreachable!(),
+                };
+                *slot = Slot::Pending(easy);
+                self.pending.push_front(token);
+            }
+        }
+    }
+
+    /// The earliest time at which a sleeping transfer is due to wake up, if
+    /// any are waiting out a retry backoff. Used to size the `Multi::wait`
+    /// timeout so the loop neither busy-spins nor oversleeps a retry.
+    fn next_wake(&self) -> Option<Instant> {
+        self.slots
+            .iter()
+            .filter_map(|slot| match slot {
+                Slot::Sleeping { ready_at, .. } => Some(*ready_at),
+                _ => None,
+            })
+            .min()
+    }
+
+    /// Exponential backoff with jitter for the `attempt`'th retry (0-indexed).
+    fn backoff_delay(attempt: u32) -> Duration {
+        let exp_millis = RETRY_BASE_DELAY
+            .as_millis()
+            .saturating_mul(1u128 << attempt.min(16)) as u64;
+        let jitter = rand::thread_rng().gen_range(0..=(exp_millis / 4).max(1));
+        Duration::from_millis(exp_millis + jitter)
+    }
+
+    /// Drive all of the Easy2 handles in the Multi stack to completion,
+    /// reacting to commands received on `commands` and keeping `status`
+    /// up to date for live introspection via `MultiDriverHandle`.
     ///
     /// Returns all of the Easy2 handles in the Multi stack in the order
-    /// they were added, along with the indices of any failed transfers
-    /// (along with the corresponding error code).
-    pub(super) fn perform(&mut self) -> Fallible<MultiDriverResult<H>> {
-        let num_transfers = self.handles.len();
-        let mut in_progress = num_transfers;
+    /// they were added, along with the indices of any failed or cancelled
+    /// transfers (along with the corresponding error code for failures).
+    fn run(
+        &mut self,
+        commands: Receiver<MultiDriverCommand>,
+        status: Arc<Mutex<HashMap<Token, TransferState>>>,
+    ) -> Fallible<MultiDriverResult<H>> {
         let mut failed = Vec::new();
+        let mut cancelled = Vec::new();
         let mut i = 0;
 
         loop {
+            self.drain_commands(&commands, &mut cancelled, &status)?;
+            self.wake_sleeping();
+            self.admit_pending()?;
+
             log::trace!(
-                "Iteration {}: {}/{} transfers complete",
+                "Iteration {}: {} active, {} pending, {} sleeping",
                 i,
-                num_transfers - in_progress,
-                num_transfers
+                self.active_count(),
+                self.pending.len(),
+                self.slots
+                    .iter()
+                    .filter(|s| matches!(s, Slot::Sleeping { .. }))
+                    .count(),
             );
             i += 1;
 
-            in_progress = self.multi.perform()? as usize;
+            let in_progress = self.multi.perform()? as usize;
 
             // Check for messages; a message indicates a transfer completed (successfully or not).
             let mut should_report_progress = false;
+            let mut message_results = Vec::new();
             self.multi.messages(|msg| {
                 let token = msg.token().unwrap();
                 log::trace!("Got message for transfer {}", token);
@@ -127,13 +682,7 @@ impl<H: Handler> MultiDriver<H> {
                 should_report_progress = true;
 
                 match msg.result() {
-                    Some(Ok(())) => {
-                        log::trace!("Transfer {} complete", token);
-                    }
-                    Some(Err(e)) => {
-                        log::trace!("Transfer {} failed: {}", token, &e);
-                        failed.push((token, e));
-                    }
+                    Some(result) => message_results.push((token, result)),
                     None => {
                         // Theoretically this should never happen because
                         // this closure is only called on completion.
@@ -142,7 +691,109 @@ impl<H: Handler> MultiDriver<H> {
                 }
             });
 
-            if self.fail_early && failed.len() > 0 {
+            for (token, result) in message_results {
+                let handle = match self.slots.get_mut(token) {
+                    Some(slot @ Slot::Active(_)) => match mem::replace(slot, Slot::Removed) {
+                        Slot::Active(handle) => handle,
+                        _ => unreachable!(),
+                    },
+                    _ => continue,
+                };
+
+                match result {
+                    Ok(()) => {
+                        log::trace!("Transfer {} complete", token);
+                        let bytes = Self::bytes_downloaded(&handle);
+                        let easy = self.multi.remove2(handle)?;
+                        self.slots[token] = Slot::Finished(easy);
+                        status.lock().unwrap().insert(token, TransferState::Completed);
+                        if let Some(ref events) = self.events {
+                            let _ = events.send(TransferEvent {
+                                token,
+                                result: Ok(()),
+                                bytes,
+                            });
+                        }
+                    }
+                    Err(e) => {
+                        let response_code = Self::response_code(&handle);
+                        let bytes = Self::bytes_downloaded(&handle);
+                        let mut easy = self.multi.remove2(handle)?;
+                        let attempt = *self.attempts.get(&token).unwrap_or(&0);
+
+                        let should_retry = attempt < self.max_retries.unwrap_or(0)
+                            && (self.retryable)(&e, response_code);
+
+                        if should_retry {
+                            let next_attempt = attempt + 1;
+                            self.attempts.insert(token, next_attempt);
+
+                            if let Some(ref range_resume) = self.range_resume {
+                                let received = range_resume(easy.get_ref());
+                                if received > 0 {
+                                    let _ = easy.range(&format!("{}-", received));
+                                }
+                            }
+
+                            let delay = Self::backoff_delay(attempt);
+                            log::debug!(
+                                "Transfer {} failed ({}); retrying (attempt {} of {}) in {:?}",
+                                token,
+                                &e,
+                                next_attempt,
+                                self.max_retries.unwrap_or(0),
+                                delay,
+                            );
+                            self.slots[token] = Slot::Sleeping {
+                                easy,
+                                ready_at: Instant::now() + delay,
+                            };
+                            status
+                                .lock()
+                                .unwrap()
+                                .insert(token, TransferState::Sleeping { attempt: next_attempt });
+                        } else {
+                            log::trace!("Transfer {} failed: {}", token, &e);
+                            self.slots[token] = Slot::Finished(easy);
+                            status
+                                .lock()
+                                .unwrap()
+                                .insert(token, TransferState::Failed(e.code()));
+                            if let Some(ref events) = self.events {
+                                let _ = events.send(TransferEvent {
+                                    token,
+                                    result: Err(e.code()),
+                                    bytes,
+                                });
+                            }
+                            failed.push((token, e));
+                        }
+                    }
+                }
+            }
+
+            // A completion may have freed up room in the concurrency window.
+            self.admit_pending()?;
+
+            {
+                let mut status = status.lock().unwrap();
+                for (token, slot) in self.slots.iter().enumerate() {
+                    match slot {
+                        Slot::Active(handle) => {
+                            status.insert(token, TransferState::InProgress {
+                                bytes: Self::bytes_downloaded(handle),
+                            });
+                        }
+                        Slot::Pending(_) => {
+                            status.insert(token, TransferState::Idle);
+                        }
+                        Slot::Paused(_) | Slot::Sleeping { .. } | Slot::Finished(_)
+                        | Slot::Removed => {}
+                    }
+                }
+            }
+
+            if self.fail_early && !failed.is_empty() {
                 log::debug!("At least one transfer failed; aborting.");
                 break;
             }
@@ -153,21 +804,40 @@ impl<H: Handler> MultiDriver<H> {
                 }
             }
 
-            if in_progress == 0 {
-                log::debug!("All transfers finished successfully.");
+            let next_wake = self.next_wake();
+            if self.active_count() == 0
+                && self.pending.is_empty()
+                && self.paused_count() == 0
+                && next_wake.is_none()
+                && in_progress == 0
+            {
+                log::debug!("All transfers finished.");
                 break;
             }
 
-            let timeout = self.multi.get_timeout()?.unwrap_or(DEFAULT_TIMEOUT);
-            log::trace!("Waiting for I/O with timeout: {:?}", &timeout);
+            let curl_timeout = self.multi.get_timeout()?.unwrap_or(DEFAULT_TIMEOUT);
+            // Never wait past the next retry's wake-up time, so a batch
+            // that's entirely sleeping out a backoff doesn't idle past it;
+            // and never wait past one second so pause/resume/cancel
+            // commands are applied promptly.
+            let mut wait_timeout = curl_timeout.min(Duration::from_secs(1));
+            if let Some(ready_at) = next_wake {
+                wait_timeout = wait_timeout.min(ready_at.saturating_duration_since(Instant::now()));
+            }
+            log::trace!("Waiting for I/O with timeout: {:?}", &wait_timeout);
 
-            let num_active_transfers = self.multi.wait(&mut [], Duration::from_secs(1))?;
+            let num_active_transfers = self.multi.wait(&mut [], wait_timeout)?;
             if num_active_transfers == 0 {
                 log::trace!("Timed out waiting for I/O; polling active transfers anyway.");
             }
         }
 
         let handles = self.remove_all()?;
-        Ok(MultiDriverResult { handles, failed })
+        Ok(MultiDriverResult {
+            handles,
+            failed,
+            cancelled,
+            retries: self.attempts.clone(),
+        })
     }
 }